@@ -16,25 +16,23 @@ function logFindPosError(name) {
     console.log("【" +name + "】标志位置查找失败");
 }
 
-function logFindPosAndClickInfo(name) {
-    console.log("【" +name + "】标志位置（" + findX() + ", " + findY() + "), 并点击");
+function logFindPosAndClickInfo(name, match) {
+    console.log("【" +name + "】标志位置（" + match.x + ", " + match.y + "), 并点击");
 }
 
 function hasJxTzFlag() {
     var findFlagName = "继续挑战";
     logStartFlag(findFlagName);
-    var ncc = findTemplate("assets/zhenfa_jxtz.png", 427, 1396, 390, 235);
-    if (ncc>0.8) {
-        var click_x = findX();
-        var click_y = findY();
+    var jxtz = locate("assets/zhenfa_jxtz.png", 427, 1396, 390, 235);
+    if (jxtz && jxtz.score>0.8) {
         if (!kill_all) {
             if (hasEndFlag()){
                 console.log("最后一局，主动退出！。。。。。");
                 exit(0);
             }
         }
-        click(click_x+(240/2), click_y+(86/2));
-        logFindPosAndClickInfo(findFlagName);
+        jxtz.click();
+        logFindPosAndClickInfo(findFlagName, jxtz);
         return true;
     }
     logFindPosError(findFlagName);
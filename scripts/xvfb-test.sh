@@ -0,0 +1,17 @@
+#!/bin/sh
+# 在 Xvfb 中运行 X11 集成测试（tests/x11.rs）：scripts/xvfb-test.sh
+# 需要 Xvfb 与 libxdo；DISPLAY_NUM 可以指定显示编号，默认 99
+set -e
+
+DISPLAY_NUM=${DISPLAY_NUM:-99}
+Xvfb ":$DISPLAY_NUM" -screen 0 1024x768x24 -nolisten tcp &
+XVFB_PID=$!
+trap 'kill $XVFB_PID' EXIT INT TERM
+
+# 等待 X 服务器就绪
+for _ in 1 2 3 4 5 6 7 8 9 10; do
+    [ -e "/tmp/.X11-unix/X$DISPLAY_NUM" ] && break
+    sleep 0.5
+done
+
+DISPLAY=":$DISPLAY_NUM" cargo test --test x11 -- --ignored "$@"
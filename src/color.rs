@@ -0,0 +1,126 @@
+//! 像素颜色查询与多点找色
+//!
+//! 只比较 RGB，忽略透明度；容差为每个通道允许的最大差值（0-255）。
+//! 逐行并行扫描，结果仍按从上到下、从左到右的顺序返回。
+
+use image::{Rgb, Rgba, RgbaImage};
+use rayon::prelude::*;
+
+/// 多点找色中的一个点：相对基准点的偏移与颜色
+pub type ColorPoint = (i32, i32, Rgb<u8>);
+
+/// 解析 "#RRGGBB"、"0xRRGGBB" 或 "RRGGBB" 形式的颜色
+pub fn parse_hex(text: &str) -> Option<Rgb<u8>> {
+    let text = text.trim();
+    let hex = text
+        .strip_prefix('#')
+        .or_else(|| text.strip_prefix("0x"))
+        .or_else(|| text.strip_prefix("0X"))
+        .unwrap_or(text);
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok().map(from_u32)
+}
+
+/// 0xRRGGBB 形式的整数转换为颜色
+pub fn from_u32(value: u32) -> Rgb<u8> {
+    Rgb([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+/// 颜色的 "#RRGGBB" 表示
+pub fn to_hex(pixel: &Rgba<u8>) -> String {
+    format!("#{:02X}{:02X}{:02X}", pixel[0], pixel[1], pixel[2])
+}
+
+/// 像素与颜色每个通道的差都不超过容差
+pub fn matches(pixel: &Rgba<u8>, color: Rgb<u8>, tolerance: u8) -> bool {
+    (0..3).all(|c| pixel[c].abs_diff(color[c]) <= tolerance)
+}
+
+/// 查找颜色相符的像素，max_results 为 0 时返回全部
+pub fn find_color(img: &RgbaImage, color: Rgb<u8>, tolerance: u8, max_results: usize) -> Vec<(u32, u32)> {
+    let row = |y: u32| (0..img.width()).filter(move |&x| matches(img.get_pixel(x, y), color, tolerance)).map(move |x| (x, y));
+    if max_results == 1 {
+        return (0..img.height()).into_par_iter().find_map_first(|y| row(y).next()).into_iter().collect();
+    }
+    let found: Vec<(u32, u32)> = (0..img.height()).into_par_iter().flat_map_iter(row).collect();
+    match max_results {
+        0 => found,
+        n => found.into_iter().take(n).collect(),
+    }
+}
+
+/// 多点找色：基准点颜色相符，且每个偏移点都在图像内、颜色相符，返回第一个基准点
+pub fn find_multi_color(img: &RgbaImage, base: Rgb<u8>, points: &[ColorPoint], tolerance: u8) -> Option<(u32, u32)> {
+    let (width, height) = (img.width() as i64, img.height() as i64);
+    let fits = |x: u32, y: u32| {
+        points.iter().all(|&(dx, dy, color)| {
+            let (px, py) = (x as i64 + dx as i64, y as i64 + dy as i64);
+            px >= 0 && py >= 0 && px < width && py < height && matches(img.get_pixel(px as u32, py as u32), color, tolerance)
+        })
+    };
+    (0..img.height()).into_par_iter().find_map_first(|y| {
+        (0..img.width()).find(|&x| matches(img.get_pixel(x, y), base, tolerance) && fits(x, y)).map(|x| (x, y))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb<u8> = Rgb([200, 30, 30]);
+    const BLUE: Rgb<u8> = Rgb([20, 40, 220]);
+
+    /// 灰色背景，(x, y) 列表中的点为给定颜色
+    fn image(points: &[(u32, u32, Rgb<u8>)]) -> RgbaImage {
+        let mut img = RgbaImage::from_pixel(40, 30, Rgba([90, 90, 90, 255]));
+        for &(x, y, color) in points {
+            img.put_pixel(x, y, Rgba([color[0], color[1], color[2], 255]));
+        }
+        img
+    }
+
+    #[test]
+    fn parses_hex_and_numbers() {
+        assert_eq!(parse_hex("#3A8EE6"), Some(Rgb([0x3A, 0x8E, 0xE6])));
+        assert_eq!(parse_hex("0x3a8ee6"), Some(Rgb([0x3A, 0x8E, 0xE6])));
+        assert_eq!(parse_hex(" 3A8EE6 "), Some(Rgb([0x3A, 0x8E, 0xE6])));
+        assert_eq!(parse_hex("#3A8EE"), None);
+        assert_eq!(parse_hex("#3A8EGG"), None);
+        assert_eq!(from_u32(0xFF8000), Rgb([255, 128, 0]));
+        assert_eq!(to_hex(&Rgba([255, 128, 0, 7])), "#FF8000");
+    }
+
+    #[test]
+    fn tolerance_is_per_channel() {
+        let pixel = Rgba([100, 150, 200, 0]);
+        assert!(matches(&pixel, Rgb([100, 150, 200]), 0));
+        assert!(matches(&pixel, Rgb([110, 140, 205]), 10));
+        assert!(!matches(&pixel, Rgb([111, 150, 200]), 10));
+        assert!(!matches(&pixel, Rgb([100, 150, 189]), 10));
+    }
+
+    #[test]
+    fn find_color_scans_rows_in_order() {
+        let img = image(&[(30, 2, RED), (5, 9, RED), (20, 9, Rgb([205, 25, 35])), (1, 20, RED)]);
+        assert_eq!(find_color(&img, RED, 0, 1), vec![(30, 2)]);
+        assert_eq!(find_color(&img, RED, 0, 0), vec![(30, 2), (5, 9), (1, 20)]);
+        assert_eq!(find_color(&img, RED, 5, 0), vec![(30, 2), (5, 9), (20, 9), (1, 20)]);
+        assert_eq!(find_color(&img, RED, 5, 2), vec![(30, 2), (5, 9)]);
+        assert!(find_color(&img, BLUE, 10, 0).is_empty());
+    }
+
+    #[test]
+    fn multi_color_checks_every_offset() {
+        // (10, 5) 的右侧没有蓝点，(12, 15) 才满足；(39, 25) 的偏移点超出图像
+        let img = image(&[(10, 5, RED), (12, 15, RED), (15, 15, BLUE), (12, 17, RED), (39, 25, RED)]);
+        let points = [(3, 0, BLUE), (0, 2, RED)];
+        assert_eq!(find_multi_color(&img, RED, &points, 0), Some((12, 15)));
+        assert_eq!(find_multi_color(&img, RED, &[], 0), Some((10, 5)));
+        assert_eq!(find_multi_color(&img, RED, &[(1, 0, Rgb([90, 90, 90]))], 0), Some((10, 5)));
+        let img = image(&[(39, 25, RED)]);
+        assert_eq!(find_multi_color(&img, RED, &[(1, 0, Rgb([90, 90, 90]))], 0), None);
+        assert_eq!(find_multi_color(&img, RED, &[(-40, 0, Rgb([90, 90, 90]))], 0), None);
+    }
+}
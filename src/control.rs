@@ -0,0 +1,194 @@
+//! 运行控制：暂停 / 继续 / 中止
+//!
+//! 默认与示例脚本的约定一致，LControl+A 暂停、LControl+S 继续，LControl+LShift+Q 中止，
+//! 可以用命令行选项或 setControlKeys 修改。按键由热键后台线程检测（见 hotkeys），组合键按下的一刻生效。
+//! 暂停期间点击、查找、sleep 和等待函数都阻塞到继续为止，暂停的时长不计入 sleep 和等待的超时。
+//! 中止后的第一次调用抛出错误，finally 块照常执行，其中的 keyUp / mouseUp 仍然生效；
+//! 之后再调用其他函数（例如 catch 住错误继续循环）时，松开脚本按住的键和鼠标按键后直接退出进程。
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use device_query::Keycode;
+use enigo::{Button, Direction, Key};
+use log::info;
+use once_cell::sync::Lazy;
+
+use crate::platform;
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+static ABORTED: AtomicBool = AtomicBool::new(false);
+/// 中止错误是否已经抛出过
+static ABORT_THROWN: AtomicBool = AtomicBool::new(false);
+/// 脚本按下还没松开的键盘按键与鼠标按键，按按下顺序排列
+static HELD_KEYS: Lazy<Mutex<Vec<Key>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static HELD_BUTTONS: Lazy<Mutex<Vec<Button>>> = Lazy::new(|| Mutex::new(Vec::new()));
+
+/// 暂停、继续、中止的组合键，为空时不启用
+#[derive(Debug, Clone)]
+pub struct ControlKeys {
+    pub pause: Vec<Keycode>,
+    pub resume: Vec<Keycode>,
+    pub abort: Vec<Keycode>,
+}
+
+impl Default for ControlKeys {
+    fn default() -> Self {
+        ControlKeys {
+            pause: vec![Keycode::LControl, Keycode::A],
+            resume: vec![Keycode::LControl, Keycode::S],
+            abort: vec![Keycode::LControl, Keycode::LShift, Keycode::Q],
+        }
+    }
+}
+
+/// 暂停状态的变化，由脚本线程在安全点取出并调用 onPause / onResume 回调
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Paused,
+    Resumed,
+}
+
+static KEYS: Lazy<Mutex<ControlKeys>> = Lazy::new(|| Mutex::new(ControlKeys::default()));
+/// 上次检测时暂停、继续、中止组合键是否按下，用于只在按下的一刻触发
+static HELD: Mutex<[bool; 3]> = Mutex::new([false; 3]);
+static EVENTS: Lazy<Mutex<VecDeque<Event>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+
+/// 暂停时检查状态的间隔
+const PAUSED_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+pub fn keys() -> ControlKeys {
+    KEYS.lock().unwrap().clone()
+}
+
+pub fn set_keys(keys: ControlKeys) {
+    info!("运行控制热键: {:?}", keys);
+    *KEYS.lock().unwrap() = keys;
+}
+
+fn key_names(keys: &[Keycode]) -> String {
+    keys.iter().map(|key| format!("{:?}", key)).collect::<Vec<_>>().join("+")
+}
+
+/// 根据当前按下的按键更新状态，由热键后台线程定期调用。
+/// 暂停与继续设为同一组合键时，每按一次切换一次
+pub fn update(pressed: &[Keycode]) {
+    let keys = KEYS.lock().unwrap().clone();
+    let combos = [&keys.pause, &keys.resume, &keys.abort];
+    let down = combos.map(|combo| !combo.is_empty() && combo.iter().all(|key| pressed.contains(key)));
+    let pressed_now = {
+        let mut held = HELD.lock().unwrap();
+        let pressed_now = [0, 1, 2].map(|i| down[i] && !held[i]);
+        *held = down;
+        pressed_now
+    };
+    if pressed_now[0] && keys.pause == keys.resume {
+        info!("[{}] 按下", key_names(&keys.pause));
+        if is_paused() {
+            resume();
+        } else {
+            pause();
+        }
+    } else {
+        if pressed_now[0] {
+            info!("[{}] 按下", key_names(&keys.pause));
+            pause();
+        }
+        if pressed_now[1] {
+            info!("[{}] 按下", key_names(&keys.resume));
+            resume();
+        }
+    }
+    if pressed_now[2] {
+        info!("[{}] 按下", key_names(&keys.abort));
+        abort();
+    }
+}
+
+pub fn pause() {
+    if !PAUSED.swap(true, Ordering::SeqCst) {
+        info!("已暂停！");
+        EVENTS.lock().unwrap().push_back(Event::Paused);
+    }
+}
+
+pub fn resume() {
+    if PAUSED.swap(false, Ordering::SeqCst) {
+        info!("继续运行！");
+        EVENTS.lock().unwrap().push_back(Event::Resumed);
+    }
+}
+
+pub fn abort() {
+    if !ABORTED.swap(true, Ordering::SeqCst) {
+        info!("中止脚本！");
+    }
+}
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Ordering::SeqCst)
+}
+
+pub fn is_aborted() -> bool {
+    ABORTED.load(Ordering::SeqCst)
+}
+
+/// 已中止且中止错误还没抛出过时返回 true，只返回一次
+pub fn take_abort() -> bool {
+    is_aborted() && !ABORT_THROWN.swap(true, Ordering::SeqCst)
+}
+
+/// 发送键盘按键事件，并记录按住的键
+pub fn send_key(key: Key, direction: Direction) -> bool {
+    let ok = platform::current().key(key, direction);
+    let mut held = HELD_KEYS.lock().unwrap();
+    match direction {
+        Direction::Press if ok && !held.contains(&key) => held.push(key),
+        Direction::Release => held.retain(|k| *k != key),
+        _ => {}
+    }
+    ok
+}
+
+/// 发送鼠标按键事件，并记录按住的按键
+pub fn send_button(button: Button, direction: Direction) -> bool {
+    let ok = platform::current().mouse_button(button, direction);
+    let mut held = HELD_BUTTONS.lock().unwrap();
+    match direction {
+        Direction::Press if ok && !held.contains(&button) => held.push(button),
+        Direction::Release => held.retain(|b| *b != button),
+        _ => {}
+    }
+    ok
+}
+
+/// 按相反顺序松开脚本按住的所有鼠标按键和键盘按键
+pub fn release_held() {
+    let buttons: Vec<Button> = HELD_BUTTONS.lock().unwrap().drain(..).collect();
+    for button in buttons.into_iter().rev() {
+        info!("松开鼠标按键: {:?}", button);
+        platform::current().mouse_button(button, Direction::Release);
+    }
+    let keys: Vec<Key> = HELD_KEYS.lock().unwrap().drain(..).collect();
+    for key in keys.into_iter().rev() {
+        info!("松开按键: {:?}", key);
+        platform::current().key(key, Direction::Release);
+    }
+}
+
+/// 取出尚未处理的暂停 / 继续事件，按发生顺序排列
+pub fn take_events() -> Vec<Event> {
+    EVENTS.lock().unwrap().drain(..).collect()
+}
+
+/// 暂停期间阻塞，中止时立即返回，返回阻塞的时长
+pub fn wait_while_paused() -> Duration {
+    let start = Instant::now();
+    while is_paused() && !is_aborted() {
+        thread::sleep(PAUSED_POLL_INTERVAL);
+    }
+    start.elapsed()
+}
@@ -0,0 +1,432 @@
+//! 基于特征点的匹配
+//!
+//! 与 ORB 的思路相同：在图像金字塔的每一层用 FAST 检测角点，用灰度质心确定方向，
+//! 计算随方向旋转的 BRIEF 二进制描述子；模板与截图的描述子按汉明距离配对后，
+//! 用 RANSAC 估计相似变换（平移、缩放、旋转），因此可以容忍缩放、旋转和部分遮挡。
+
+use std::collections::HashSet;
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GrayImage};
+use imageproc::corners::corners_fast9;
+use imageproc::filter::gaussian_blur_f32;
+use log::info;
+use rayon::prelude::*;
+
+/// 特征匹配选项
+#[derive(Debug, Clone)]
+pub struct FeatureOptions {
+    /// FAST 角点阈值，越小角点越多
+    pub fast_threshold: u8,
+    /// 截图中每层最多保留的角点数
+    pub max_features: usize,
+    /// 至少需要的内点数，少于该值视为未找到
+    pub min_inliers: usize,
+}
+
+impl Default for FeatureOptions {
+    fn default() -> Self {
+        FeatureOptions {
+            fast_threshold: 20,
+            max_features: 500,
+            min_inliers: 8,
+        }
+    }
+}
+
+/// 特征匹配结果，相似变换下矩形外接框的中心就是模板中心的位置
+#[derive(Debug, Clone, Copy)]
+pub struct FeatureMatch {
+    /// 变换后模板的外接矩形
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub scale: f32,
+    /// 旋转角度，度，顺时针为正
+    pub angle: f32,
+    /// 内点数占模板特征点数的比例
+    pub score: f32,
+}
+
+/// 金字塔层数与相邻层的缩放比例
+const PYRAMID_LEVELS: u32 = 8;
+const PYRAMID_SCALE: f32 = 1.2;
+/// 方向与描述子使用的圆形邻域半径
+const PATCH_RADIUS: i32 = 15;
+/// BRIEF 描述子的位数
+const DESCRIPTOR_BITS: usize = 256;
+/// 配对时最佳与次佳距离之比的上限（Lowe 比值检验）
+const RATIO_TEST: f32 = 0.9;
+/// 配对允许的最大汉明距离
+const MAX_HAMMING: u32 = 90;
+/// RANSAC 迭代次数与内点距离（原图像素）
+const RANSAC_ITERATIONS: usize = 1000;
+const RANSAC_INLIER_DISTANCE: f32 = 4.0;
+
+type Point = (f32, f32);
+/// 模板点与截图点的配对，BRIEF 采样点对也用同样的形式
+type Pair = (Point, Point);
+
+/// 特征点：坐标为原图坐标，描述子为 256 位
+struct Keypoint {
+    x: f32,
+    y: f32,
+    descriptor: [u64; DESCRIPTOR_BITS / 64],
+}
+
+/// 固定种子的线性同余随机数，保证采样模式和 RANSAC 结果可复现
+struct Lcg(u64);
+
+impl Lcg {
+    fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 33) as u32
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        self.next() as usize % n
+    }
+}
+
+/// BRIEF 采样点对，在半径内按近似高斯分布生成
+fn brief_pattern() -> Vec<Pair> {
+    let mut rng = Lcg(0x5eed);
+    let radius = PATCH_RADIUS as f32;
+    let mut sample = || {
+        loop {
+            // 四个均匀分布之和近似高斯分布，标准差约为半径的 0.4 倍
+            let gaussian = |rng: &mut Lcg| (0..4).map(|_| rng.next() as f32 / u32::MAX as f32).sum::<f32>() - 2.0;
+            let (x, y) = (gaussian(&mut rng) * radius * 0.7, gaussian(&mut rng) * radius * 0.7);
+            if x * x + y * y <= radius * radius {
+                return (x, y);
+            }
+        }
+    };
+    (0..DESCRIPTOR_BITS).map(|_| (sample(), sample())).collect()
+}
+
+/// 坐标超出图像时取边缘像素
+fn pixel_clamped(img: &GrayImage, x: i32, y: i32) -> u8 {
+    let x = x.clamp(0, img.width() as i32 - 1) as u32;
+    let y = y.clamp(0, img.height() as i32 - 1) as u32;
+    img.get_pixel(x, y)[0]
+}
+
+/// 灰度质心方向
+fn orientation(img: &GrayImage, cx: i32, cy: i32) -> f32 {
+    let (mut m01, mut m10) = (0i64, 0i64);
+    for dy in -PATCH_RADIUS..=PATCH_RADIUS {
+        for dx in -PATCH_RADIUS..=PATCH_RADIUS {
+            if dx * dx + dy * dy > PATCH_RADIUS * PATCH_RADIUS {
+                continue;
+            }
+            let v = pixel_clamped(img, cx + dx, cy + dy) as i64;
+            m10 += dx as i64 * v;
+            m01 += dy as i64 * v;
+        }
+    }
+    (m01 as f32).atan2(m10 as f32)
+}
+
+/// 在一层图像上检测角点并计算描述子，坐标按 scale 换算回原图
+fn detect_level(img: &GrayImage, scale: f32, pattern: &[Pair], keep: Option<&dyn Fn(f32, f32) -> bool>, options: &FeatureOptions) -> Vec<Keypoint> {
+    let mut corners = corners_fast9(img, options.fast_threshold);
+    corners.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    // 简单的非极大值抑制：已保留角点的 3×3 邻域内不再保留
+    let (width, height) = (img.width() as usize, img.height() as usize);
+    let mut occupied = vec![false; width * height];
+    let mut kept = Vec::new();
+    for corner in corners {
+        let (x, y) = (corner.x as usize, corner.y as usize);
+        if occupied[y * width + x] {
+            continue;
+        }
+        if let Some(keep) = keep {
+            if !keep(x as f32 * scale, y as f32 * scale) {
+                continue;
+            }
+        }
+        for ny in y.saturating_sub(1)..(y + 2).min(height) {
+            for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                occupied[ny * width + nx] = true;
+            }
+        }
+        kept.push(corner);
+        if kept.len() >= options.max_features {
+            break;
+        }
+    }
+
+    let smoothed = gaussian_blur_f32(img, 2.0);
+    kept.par_iter()
+        .map(|corner| {
+            let (cx, cy) = (corner.x as i32, corner.y as i32);
+            let (sin, cos) = orientation(img, cx, cy).sin_cos();
+            let rotate = |(px, py): (f32, f32)| {
+                ((cx as f32 + px * cos - py * sin).round() as i32, (cy as f32 + px * sin + py * cos).round() as i32)
+            };
+            let mut descriptor = [0u64; DESCRIPTOR_BITS / 64];
+            for (i, &(p, q)) in pattern.iter().enumerate() {
+                let (px, py) = rotate(p);
+                let (qx, qy) = rotate(q);
+                if pixel_clamped(&smoothed, px, py) < pixel_clamped(&smoothed, qx, qy) {
+                    descriptor[i / 64] |= 1 << (i % 64);
+                }
+            }
+            Keypoint { x: corner.x as f32 * scale, y: corner.y as f32 * scale, descriptor }
+        })
+        .collect()
+}
+
+/// 在图像金字塔的各层检测特征点，keep 用于按原图坐标筛选（例如排除掩码外的点）
+fn detect(img: &GrayImage, keep: Option<&dyn Fn(f32, f32) -> bool>, options: &FeatureOptions) -> Vec<Keypoint> {
+    let pattern = brief_pattern();
+    let mut keypoints = Vec::new();
+    for level in 0..PYRAMID_LEVELS {
+        let scale = PYRAMID_SCALE.powi(level as i32);
+        let (width, height) = ((img.width() as f32 / scale) as u32, (img.height() as f32 / scale) as u32);
+        if width < 8 || height < 8 {
+            break;
+        }
+        let level_img = if level == 0 { img.clone() } else { image::imageops::resize(img, width, height, FilterType::Triangle) };
+        keypoints.extend(detect_level(&level_img, scale, &pattern, keep, options));
+    }
+    keypoints
+}
+
+fn hamming(a: &[u64; DESCRIPTOR_BITS / 64], b: &[u64; DESCRIPTOR_BITS / 64]) -> u32 {
+    a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum()
+}
+
+/// 为每个模板特征点找最近的截图特征点，通过比值检验的配对返回 (模板点, 截图点)
+fn match_keypoints(template: &[Keypoint], image: &[Keypoint]) -> Vec<Pair> {
+    template
+        .par_iter()
+        .filter_map(|t| {
+            let (mut best, mut second, mut best_index) = (u32::MAX, u32::MAX, 0);
+            for (i, k) in image.iter().enumerate() {
+                let distance = hamming(&t.descriptor, &k.descriptor);
+                if distance < best {
+                    second = best;
+                    best = distance;
+                    best_index = i;
+                } else if distance < second {
+                    second = distance;
+                }
+            }
+            if best > MAX_HAMMING || (second != u32::MAX && best as f32 > RATIO_TEST * second as f32) {
+                return None;
+            }
+            let k = &image[best_index];
+            Some(((t.x, t.y), (k.x, k.y)))
+        })
+        .collect()
+}
+
+/// 复数形式的相似变换 q = a·p + b，a = (a_re, a_im)
+#[derive(Debug, Clone, Copy)]
+struct Similarity {
+    a: (f32, f32),
+    b: (f32, f32),
+}
+
+impl Similarity {
+    /// 由两对点求变换
+    fn from_pairs(p1: (f32, f32), q1: (f32, f32), p2: (f32, f32), q2: (f32, f32)) -> Option<Self> {
+        let (dpx, dpy) = (p2.0 - p1.0, p2.1 - p1.1);
+        let (dqx, dqy) = (q2.0 - q1.0, q2.1 - q1.1);
+        let norm = dpx * dpx + dpy * dpy;
+        if norm < 1.0 {
+            return None;
+        }
+        // a = dq / dp
+        let a = ((dqx * dpx + dqy * dpy) / norm, (dqy * dpx - dqx * dpy) / norm);
+        let b = (q1.0 - (a.0 * p1.0 - a.1 * p1.1), q1.1 - (a.0 * p1.1 + a.1 * p1.0));
+        Some(Similarity { a, b })
+    }
+
+    /// 最小二乘拟合
+    fn fit(pairs: &[Pair]) -> Option<Self> {
+        let n = pairs.len() as f32;
+        let (pmx, pmy) = pairs.iter().fold((0.0, 0.0), |acc, (p, _)| (acc.0 + p.0 / n, acc.1 + p.1 / n));
+        let (qmx, qmy) = pairs.iter().fold((0.0, 0.0), |acc, (_, q)| (acc.0 + q.0 / n, acc.1 + q.1 / n));
+        let (mut re, mut im, mut norm) = (0.0, 0.0, 0.0);
+        for ((px, py), (qx, qy)) in pairs {
+            let (px, py, qx, qy) = (px - pmx, py - pmy, qx - qmx, qy - qmy);
+            // (q - q̄)·conj(p - p̄)
+            re += qx * px + qy * py;
+            im += qy * px - qx * py;
+            norm += px * px + py * py;
+        }
+        if norm < 1.0 {
+            return None;
+        }
+        let a = (re / norm, im / norm);
+        let b = (qmx - (a.0 * pmx - a.1 * pmy), qmy - (a.0 * pmy + a.1 * pmx));
+        Some(Similarity { a, b })
+    }
+
+    fn apply(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (self.a.0 * x - self.a.1 * y + self.b.0, self.a.0 * y + self.a.1 * x + self.b.1)
+    }
+
+    fn scale(&self) -> f32 {
+        (self.a.0 * self.a.0 + self.a.1 * self.a.1).sqrt()
+    }
+
+    /// 排除明显不合理的缩放
+    fn plausible(&self) -> bool {
+        (0.2..=5.0).contains(&self.scale())
+    }
+
+    fn inliers(&self, pairs: &[Pair]) -> Vec<Pair> {
+        pairs
+            .iter()
+            .filter(|(p, q)| {
+                let (x, y) = self.apply(*p);
+                (x - q.0).powi(2) + (y - q.1).powi(2) <= RANSAC_INLIER_DISTANCE * RANSAC_INLIER_DISTANCE
+            })
+            .copied()
+            .collect()
+    }
+}
+
+/// 落在不同截图位置上的配对数；缩放很小时大量模板点会挤到同几个截图点上，不能算作独立的内点
+fn distinct_targets(pairs: &[Pair]) -> usize {
+    pairs.iter().map(|(_, q)| (q.0.round() as i32, q.1.round() as i32)).collect::<HashSet<_>>().len()
+}
+
+/// RANSAC：随机取两对点求变换，保留内点最多的变换，再用全部内点重新拟合
+fn ransac(pairs: &[Pair]) -> Option<(Similarity, usize)> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let mut rng = Lcg(0x7a5c);
+    let mut best: Option<(Similarity, usize)> = None;
+    for _ in 0..RANSAC_ITERATIONS {
+        let (i, j) = (rng.below(pairs.len()), rng.below(pairs.len()));
+        if i == j {
+            continue;
+        }
+        let Some(model) = Similarity::from_pairs(pairs[i].0, pairs[i].1, pairs[j].0, pairs[j].1) else {
+            continue;
+        };
+        if !model.plausible() {
+            continue;
+        }
+        let count = distinct_targets(&model.inliers(pairs));
+        if best.is_none_or(|(_, c)| count > c) {
+            best = Some((model, count));
+        }
+    }
+    let (model, _) = best?;
+    let inliers = model.inliers(pairs);
+    let refined = Similarity::fit(&inliers).filter(Similarity::plausible).unwrap_or(model);
+    let count = distinct_targets(&refined.inliers(pairs));
+    Some((refined, count))
+}
+
+/// 在截图中查找模板，mask 中小于 128 的模板像素上的特征点不参与匹配
+pub fn find_feature(img: &DynamicImage, template: &DynamicImage, mask: Option<&GrayImage>, options: &FeatureOptions) -> Option<FeatureMatch> {
+    let img_gray = img.to_luma8();
+    let template_gray = template.to_luma8();
+    let keep = mask.map(|mask| {
+        move |x: f32, y: f32| {
+            let (x, y) = (x as u32, y as u32);
+            x < mask.width() && y < mask.height() && mask.get_pixel(x, y)[0] >= 128
+        }
+    });
+    let template_points = detect(&template_gray, keep.as_ref().map(|k| k as &dyn Fn(f32, f32) -> bool), options);
+    let image_points = detect(&img_gray, None, options);
+    let pairs = match_keypoints(&template_points, &image_points);
+    info!("特征点: 模板 {} 个, 截图 {} 个, 配对 {} 个", template_points.len(), image_points.len(), pairs.len());
+
+    let (model, inliers) = ransac(&pairs)?;
+    info!("RANSAC 内点: {} 个, 缩放: {}, 角度: {}", inliers, model.scale(), model.a.1.atan2(model.a.0).to_degrees());
+    if inliers < options.min_inliers.max(2) {
+        return None;
+    }
+
+    let (w, h) = (template.width() as f32, template.height() as f32);
+    let corners = [(0.0, 0.0), (w, 0.0), (0.0, h), (w, h)].map(|p| model.apply(p));
+    let min_x = corners.iter().map(|c| c.0).fold(f32::INFINITY, f32::min);
+    let min_y = corners.iter().map(|c| c.1).fold(f32::INFINITY, f32::min);
+    let max_x = corners.iter().map(|c| c.0).fold(f32::NEG_INFINITY, f32::max);
+    let max_y = corners.iter().map(|c| c.1).fold(f32::NEG_INFINITY, f32::max);
+    Some(FeatureMatch {
+        x: min_x.round() as i32,
+        y: min_y.round() as i32,
+        width: (max_x - min_x).round().max(1.0) as u32,
+        height: (max_y - min_y).round().max(1.0) as u32,
+        scale: model.scale(),
+        angle: model.a.1.atan2(model.a.0).to_degrees(),
+        score: (inliers as f32 / template_points.len().max(1) as f32).min(1.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    /// 灰色背景上随机摆放若干不同亮度的矩形
+    fn rectangles(width: u32, height: u32, count: usize, seed: u64) -> GrayImage {
+        let mut rng = Lcg(seed);
+        let mut img = GrayImage::from_pixel(width, height, Luma([110]));
+        for _ in 0..count {
+            let (w, h) = (6 + rng.below(18) as u32, 6 + rng.below(18) as u32);
+            let (x, y) = (rng.below((width - w) as usize) as u32, rng.below((height - h) as usize) as u32);
+            let value = Luma([rng.below(256) as u8]);
+            for dy in 0..h {
+                for dx in 0..w {
+                    img.put_pixel(x + dx, y + dy, value);
+                }
+            }
+        }
+        img
+    }
+
+    /// 把模板按相似变换 model 放到平坦背景上，逐像素反向映射并双线性插值
+    fn place(template: &GrayImage, model: &Similarity, width: u32, height: u32) -> GrayImage {
+        let norm = model.a.0 * model.a.0 + model.a.1 * model.a.1;
+        let (inv_re, inv_im) = (model.a.0 / norm, -model.a.1 / norm);
+        GrayImage::from_fn(width, height, |x, y| {
+            let (qx, qy) = (x as f32 - model.b.0, y as f32 - model.b.1);
+            let (px, py) = (inv_re * qx - inv_im * qy, inv_re * qy + inv_im * qx);
+            if px < 0.0 || py < 0.0 || px > (template.width() - 1) as f32 || py > (template.height() - 1) as f32 {
+                return Luma([110]);
+            }
+            let (x0, y0) = (px.floor() as i32, py.floor() as i32);
+            let (fx, fy) = (px - x0 as f32, py - y0 as f32);
+            let at = |x: i32, y: i32| pixel_clamped(template, x, y) as f32;
+            let top = at(x0, y0) * (1.0 - fx) + at(x0 + 1, y0) * fx;
+            let bottom = at(x0, y0 + 1) * (1.0 - fx) + at(x0 + 1, y0 + 1) * fx;
+            Luma([(top * (1.0 - fy) + bottom * fy).round() as u8])
+        })
+    }
+
+    #[test]
+    fn recovers_known_similarity() {
+        let template = rectangles(120, 90, 30, 1);
+        let (scale, angle) = (1.3f32, 15.0f32);
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let model = Similarity { a: (scale * cos, scale * sin), b: (100.0, 60.0) };
+        let img = place(&template, &model, 360, 300);
+        let found = find_feature(&DynamicImage::ImageLuma8(img), &DynamicImage::ImageLuma8(template), None, &FeatureOptions::default()).unwrap();
+        assert!((found.scale - scale).abs() < 0.05, "{:?}", found);
+        assert!((found.angle - angle).abs() < 2.0, "{:?}", found);
+        // 相似变换下外接矩形的中心就是模板中心
+        let (cx, cy) = model.apply((60.0, 45.0));
+        let (found_cx, found_cy) = (found.x as f32 + found.width as f32 / 2.0, found.y as f32 + found.height as f32 / 2.0);
+        assert!((found_cx - cx).abs() < 3.0 && (found_cy - cy).abs() < 3.0, "({}, {}) != ({}, {})", found_cx, found_cy, cx, cy);
+    }
+
+    #[test]
+    fn textureless_template_is_not_found() {
+        let img = rectangles(360, 300, 60, 2);
+        let template = GrayImage::from_pixel(60, 40, Luma([128]));
+        assert!(find_feature(&DynamicImage::ImageLuma8(img), &DynamicImage::ImageLuma8(template), None, &FeatureOptions::default()).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+//! 全局热键
+//!
+//! 后台线程按固定间隔读取按键状态，组合键从未全部按下变为全部按下时，把热键 id 放入队列。
+//! JavaScript 回调不能在后台线程执行，由脚本线程在安全点（sleep、等待函数的每次轮询）取出队列依次调用，
+//! 所以热键在一次长 sleep 中按下又松开也不会漏掉。
+//! 同一个线程也负责检测暂停、继续、中止热键（见 control），这些热键在后台线程中直接生效。
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::Duration;
+
+use device_query::Keycode;
+use log::info;
+use once_cell::sync::Lazy;
+
+use crate::{control, platform};
+
+/// 后台线程读取按键状态的间隔，也是 sleep 检查回调队列的间隔
+pub const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// 热键 id 与组合键
+type Hotkey = (u32, Vec<Keycode>);
+
+static HOTKEYS: Lazy<Mutex<Vec<Hotkey>>> = Lazy::new(|| Mutex::new(Vec::new()));
+static FIRED: Lazy<Mutex<VecDeque<u32>>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+static NEXT_ID: AtomicU32 = AtomicU32::new(1);
+static WATCHER: Once = Once::new();
+
+/// 启动后台线程，重复调用无影响
+pub fn start() {
+    WATCHER.call_once(|| {
+        thread::spawn(watch);
+    });
+}
+
+/// 注册组合键，返回热键 id
+pub fn register(keys: Vec<Keycode>) -> u32 {
+    let id = NEXT_ID.fetch_add(1, Ordering::SeqCst);
+    info!("注册热键 {}: {:?}", id, keys);
+    HOTKEYS.lock().unwrap().push((id, keys));
+    start();
+    id
+}
+
+/// 取消热键，已触发但还没执行的回调也一并丢弃
+pub fn unregister(id: u32) -> bool {
+    let mut hotkeys = HOTKEYS.lock().unwrap();
+    let count = hotkeys.len();
+    hotkeys.retain(|(hotkey, _)| *hotkey != id);
+    FIRED.lock().unwrap().retain(|fired| *fired != id);
+    hotkeys.len() != count
+}
+
+/// 取出已触发的热键 id，按触发顺序排列
+pub fn take_fired() -> Vec<u32> {
+    FIRED.lock().unwrap().drain(..).collect()
+}
+
+/// 把取出后没有执行的热键 id 按原顺序放回队列最前面，下一个安全点先执行它们
+pub fn requeue(ids: Vec<u32>) {
+    let mut fired = FIRED.lock().unwrap();
+    for id in ids.into_iter().rev() {
+        fired.push_front(id);
+    }
+}
+
+fn watch() {
+    let mut held = HashSet::new();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+        let keys = platform::current().pressed_keys();
+        control::update(&keys);
+        let hotkeys = HOTKEYS.lock().unwrap().clone();
+        for (id, combo) in hotkeys {
+            if combo.iter().all(|key| keys.contains(key)) {
+                if held.insert(id) {
+                    info!("热键 {} 按下: {:?}", id, combo);
+                    FIRED.lock().unwrap().push_back(id);
+                }
+            } else {
+                held.remove(&id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn requeued_ids_run_first_in_order() {
+        FIRED.lock().unwrap().push_back(5);
+        requeue(vec![3, 4]);
+        assert_eq!(take_fired(), vec![3, 4, 5]);
+        assert!(take_fired().is_empty());
+    }
+}
@@ -0,0 +1,307 @@
+//! 按键名称到 enigo 按键的转换
+//!
+//! 名称与 isKeyDown 使用的 `device_query::Keycode` 一致（LControl、S、Key1、Numpad0 ...），
+//! 同一个名字既可以检测也可以发送。字母、数字和符号键按美式键盘位置发送对应的字符。
+//! enigo 在各平台上没有统一的小键盘和右侧 Alt/Meta 按键，这些键按平台给出原始键码：
+//! X11 为 keysym，macOS 为虚拟键码。
+
+use device_query::Keycode;
+use enigo::Key;
+
+/// 解析按键名称：先按 Keycode 名称解析，否则单个字符直接作为该字符发送
+pub fn parse(name: &str) -> Option<Key> {
+    if let Ok(keycode) = name.parse::<Keycode>() {
+        return Some(to_enigo(keycode));
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Some(Key::Unicode(c)),
+        _ => None,
+    }
+}
+
+/// Keycode 对应的 enigo 按键
+pub fn to_enigo(keycode: Keycode) -> Key {
+    match keycode {
+        Keycode::Key0 => Key::Unicode('0'),
+        Keycode::Key1 => Key::Unicode('1'),
+        Keycode::Key2 => Key::Unicode('2'),
+        Keycode::Key3 => Key::Unicode('3'),
+        Keycode::Key4 => Key::Unicode('4'),
+        Keycode::Key5 => Key::Unicode('5'),
+        Keycode::Key6 => Key::Unicode('6'),
+        Keycode::Key7 => Key::Unicode('7'),
+        Keycode::Key8 => Key::Unicode('8'),
+        Keycode::Key9 => Key::Unicode('9'),
+        Keycode::A => Key::Unicode('a'),
+        Keycode::B => Key::Unicode('b'),
+        Keycode::C => Key::Unicode('c'),
+        Keycode::D => Key::Unicode('d'),
+        Keycode::E => Key::Unicode('e'),
+        Keycode::F => Key::Unicode('f'),
+        Keycode::G => Key::Unicode('g'),
+        Keycode::H => Key::Unicode('h'),
+        Keycode::I => Key::Unicode('i'),
+        Keycode::J => Key::Unicode('j'),
+        Keycode::K => Key::Unicode('k'),
+        Keycode::L => Key::Unicode('l'),
+        Keycode::M => Key::Unicode('m'),
+        Keycode::N => Key::Unicode('n'),
+        Keycode::O => Key::Unicode('o'),
+        Keycode::P => Key::Unicode('p'),
+        Keycode::Q => Key::Unicode('q'),
+        Keycode::R => Key::Unicode('r'),
+        Keycode::S => Key::Unicode('s'),
+        Keycode::T => Key::Unicode('t'),
+        Keycode::U => Key::Unicode('u'),
+        Keycode::V => Key::Unicode('v'),
+        Keycode::W => Key::Unicode('w'),
+        Keycode::X => Key::Unicode('x'),
+        Keycode::Y => Key::Unicode('y'),
+        Keycode::Z => Key::Unicode('z'),
+        Keycode::F1 => Key::F1,
+        Keycode::F2 => Key::F2,
+        Keycode::F3 => Key::F3,
+        Keycode::F4 => Key::F4,
+        Keycode::F5 => Key::F5,
+        Keycode::F6 => Key::F6,
+        Keycode::F7 => Key::F7,
+        Keycode::F8 => Key::F8,
+        Keycode::F9 => Key::F9,
+        Keycode::F10 => Key::F10,
+        Keycode::F11 => Key::F11,
+        Keycode::F12 => Key::F12,
+        Keycode::F13 => Key::F13,
+        Keycode::F14 => Key::F14,
+        Keycode::F15 => Key::F15,
+        Keycode::F16 => Key::F16,
+        Keycode::F17 => Key::F17,
+        Keycode::F18 => Key::F18,
+        Keycode::F19 => Key::F19,
+        Keycode::F20 => Key::F20,
+        Keycode::Escape => Key::Escape,
+        Keycode::Space => Key::Space,
+        Keycode::LControl => Key::LControl,
+        Keycode::RControl => Key::RControl,
+        Keycode::LShift => Key::LShift,
+        Keycode::RShift => Key::RShift,
+        Keycode::LAlt | Keycode::LOption => Key::Alt,
+        Keycode::RAlt | Keycode::ROption => right_alt(),
+        Keycode::LMeta | Keycode::Command => Key::Meta,
+        Keycode::RMeta | Keycode::RCommand => right_meta(),
+        Keycode::Enter => Key::Return,
+        Keycode::Up => Key::UpArrow,
+        Keycode::Down => Key::DownArrow,
+        Keycode::Left => Key::LeftArrow,
+        Keycode::Right => Key::RightArrow,
+        Keycode::Backspace => Key::Backspace,
+        Keycode::CapsLock => Key::CapsLock,
+        Keycode::Tab => Key::Tab,
+        Keycode::Home => Key::Home,
+        Keycode::End => Key::End,
+        Keycode::PageUp => Key::PageUp,
+        Keycode::PageDown => Key::PageDown,
+        Keycode::Insert => insert(),
+        Keycode::Delete => Key::Delete,
+        Keycode::Numpad0
+        | Keycode::Numpad1
+        | Keycode::Numpad2
+        | Keycode::Numpad3
+        | Keycode::Numpad4
+        | Keycode::Numpad5
+        | Keycode::Numpad6
+        | Keycode::Numpad7
+        | Keycode::Numpad8
+        | Keycode::Numpad9
+        | Keycode::NumpadSubtract
+        | Keycode::NumpadAdd
+        | Keycode::NumpadDivide
+        | Keycode::NumpadMultiply
+        | Keycode::NumpadEquals
+        | Keycode::NumpadEnter
+        | Keycode::NumpadDecimal => numpad(keycode),
+        Keycode::Grave => Key::Unicode('`'),
+        Keycode::Minus => Key::Unicode('-'),
+        Keycode::Equal => Key::Unicode('='),
+        Keycode::LeftBracket => Key::Unicode('['),
+        Keycode::RightBracket => Key::Unicode(']'),
+        Keycode::BackSlash => Key::Unicode('\\'),
+        Keycode::Semicolon => Key::Unicode(';'),
+        Keycode::Apostrophe => Key::Unicode('\''),
+        Keycode::Comma => Key::Unicode(','),
+        Keycode::Dot => Key::Unicode('.'),
+        Keycode::Slash => Key::Unicode('/'),
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn right_alt() -> Key {
+    Key::ROption
+}
+
+#[cfg(not(target_os = "macos"))]
+fn right_alt() -> Key {
+    // XK_Alt_R
+    Key::Other(0xffea)
+}
+
+#[cfg(target_os = "macos")]
+fn right_meta() -> Key {
+    Key::RCommand
+}
+
+#[cfg(not(target_os = "macos"))]
+fn right_meta() -> Key {
+    // XK_Super_R
+    Key::Other(0xffec)
+}
+
+#[cfg(target_os = "macos")]
+fn insert() -> Key {
+    // Mac 键盘在 Insert 的位置上是 Help 键
+    Key::Help
+}
+
+#[cfg(not(target_os = "macos"))]
+fn insert() -> Key {
+    Key::Insert
+}
+
+/// 小键盘按键，参数只会是 Numpad 开头的 Keycode
+#[cfg(target_os = "macos")]
+fn numpad(keycode: Keycode) -> Key {
+    // kVK_ANSI_Keypad*
+    Key::Other(match keycode {
+        Keycode::Numpad0 => 0x52,
+        Keycode::Numpad1 => 0x53,
+        Keycode::Numpad2 => 0x54,
+        Keycode::Numpad3 => 0x55,
+        Keycode::Numpad4 => 0x56,
+        Keycode::Numpad5 => 0x57,
+        Keycode::Numpad6 => 0x58,
+        Keycode::Numpad7 => 0x59,
+        Keycode::Numpad8 => 0x5b,
+        Keycode::Numpad9 => 0x5c,
+        Keycode::NumpadSubtract => 0x4e,
+        Keycode::NumpadAdd => 0x45,
+        Keycode::NumpadDivide => 0x4b,
+        Keycode::NumpadMultiply => 0x43,
+        Keycode::NumpadEquals => 0x51,
+        Keycode::NumpadEnter => 0x4c,
+        _ => 0x41,
+    })
+}
+
+/// 小键盘按键，参数只会是 Numpad 开头的 Keycode
+#[cfg(not(target_os = "macos"))]
+fn numpad(keycode: Keycode) -> Key {
+    // XK_KP_*
+    Key::Other(match keycode {
+        Keycode::Numpad0 => 0xffb0,
+        Keycode::Numpad1 => 0xffb1,
+        Keycode::Numpad2 => 0xffb2,
+        Keycode::Numpad3 => 0xffb3,
+        Keycode::Numpad4 => 0xffb4,
+        Keycode::Numpad5 => 0xffb5,
+        Keycode::Numpad6 => 0xffb6,
+        Keycode::Numpad7 => 0xffb7,
+        Keycode::Numpad8 => 0xffb8,
+        Keycode::Numpad9 => 0xffb9,
+        Keycode::NumpadSubtract => 0xffad,
+        Keycode::NumpadAdd => 0xffab,
+        Keycode::NumpadDivide => 0xffaf,
+        Keycode::NumpadMultiply => 0xffaa,
+        Keycode::NumpadEquals => 0xffbd,
+        Keycode::NumpadEnter => 0xff8d,
+        _ => 0xffae,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Keycode 的全部取值，device_query 没有提供枚举所有取值的方法
+    const ALL_KEYCODES: [Keycode; 112] = {
+        use Keycode::*;
+        [
+            Key0, Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9,
+            A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+            F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15, F16, F17, F18, F19, F20,
+            Escape, Space, LControl, RControl, LShift, RShift, LAlt, RAlt, Command, RCommand, LOption, ROption, LMeta, RMeta,
+            Enter, Up, Down, Left, Right, Backspace, CapsLock, Tab, Home, End, PageUp, PageDown, Insert, Delete,
+            Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+            NumpadSubtract, NumpadAdd, NumpadDivide, NumpadMultiply, NumpadEquals, NumpadEnter, NumpadDecimal,
+            Grave, Minus, Equal, LeftBracket, RightBracket, BackSlash, Semicolon, Apostrophe, Comma, Dot, Slash,
+        ]
+    };
+
+    #[test]
+    fn every_keycode_name_round_trips() {
+        for keycode in ALL_KEYCODES {
+            let name = keycode.to_string();
+            assert_eq!(name.parse::<Keycode>(), Ok(keycode), "{}", name);
+            assert!(parse(&name).is_some(), "{}", name);
+        }
+    }
+
+    /// 没有统一 enigo 按键的名称在本平台上的按键
+    #[cfg(not(target_os = "macos"))]
+    fn platform_keys() -> [(&'static str, Key); 6] {
+        // X11 keysym
+        [
+            ("RAlt", Key::Other(0xffea)),
+            ("RMeta", Key::Other(0xffec)),
+            ("Insert", Key::Insert),
+            ("Numpad0", Key::Other(0xffb0)),
+            ("NumpadEnter", Key::Other(0xff8d)),
+            ("NumpadDecimal", Key::Other(0xffae)),
+        ]
+    }
+
+    #[cfg(target_os = "macos")]
+    fn platform_keys() -> [(&'static str, Key); 6] {
+        // macOS 虚拟键码
+        [
+            ("RAlt", Key::ROption),
+            ("RMeta", Key::RCommand),
+            ("Insert", Key::Help),
+            ("Numpad0", Key::Other(0x52)),
+            ("NumpadEnter", Key::Other(0x4c)),
+            ("NumpadDecimal", Key::Other(0x41)),
+        ]
+    }
+
+    #[test]
+    fn names_map_to_expected_keys() {
+        let common = [
+            ("A", Key::Unicode('a')),
+            ("Z", Key::Unicode('z')),
+            ("Key1", Key::Unicode('1')),
+            ("Key0", Key::Unicode('0')),
+            ("Grave", Key::Unicode('`')),
+            ("BackSlash", Key::Unicode('\\')),
+            ("Apostrophe", Key::Unicode('\'')),
+            ("Enter", Key::Return),
+            ("LAlt", Key::Alt),
+            ("LOption", Key::Alt),
+            ("Command", Key::Meta),
+            ("LControl", Key::LControl),
+            ("Up", Key::UpArrow),
+            ("F20", Key::F20),
+        ];
+        for (name, key) in common.into_iter().chain(platform_keys()) {
+            assert_eq!(parse(name), Some(key), "{}", name);
+        }
+    }
+
+    #[test]
+    fn single_characters_and_unknown_names() {
+        assert_eq!(parse("A"), Some(Key::Unicode('a')));
+        assert_eq!(parse("a"), Some(Key::Unicode('a')));
+        assert_eq!(parse("中"), Some(Key::Unicode('中')));
+        assert_eq!(parse("LControl"), Some(Key::LControl));
+        assert_eq!(parse("Ctrl"), None);
+        assert_eq!(parse(""), None);
+    }
+}
@@ -1,159 +1,57 @@
+mod color;
+mod control;
+mod features;
+mod hotkeys;
+mod keys;
+mod matcher;
+mod motion;
+mod platform;
+mod preprocess;
+mod templates;
+mod window_query;
+
 use crate::string::String;
 use std::time::Instant;
 use std::{env, process};
 use std::fs;
 use std::io::Write;
 use std::string;
-use cocoa::base::{id, nil};
-use xcap::Window;
 
-extern crate objc;
-use objc::runtime::{Class, Object};
-use objc::{class, msg_send, sel, sel_impl};
-use image::{open, DynamicImage, GenericImage, ImageBuffer, Luma, Rgba};
+use image::{open, DynamicImage, GenericImage, GrayImage, Rgb, Rgba, RgbaImage};
+use image::imageops::FilterType;
 use image::GenericImageView;
 use std::{thread, time};
 use std::error::Error;
 use std::ptr::null_mut;
-use rayon::prelude::*; // 引入 rayon 的并行迭代器
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
-use std::sync::atomic::{AtomicBool, Ordering};
 use once_cell::sync::Lazy;
+use rayon::prelude::*;
 
-use boa::{Context, JsResult, JsValue, object::ObjectInitializer, property::Attribute};
+use boa::{Context, JsResult, JsValue, object::{JsObject, ObjectInitializer}, property::Attribute};
 use boa::JsValue::Null;
 use log::{info, debug, warn, error};
 
 use enigo::{
     Button,
-    Direction::{Click, Press, Release},
-    Enigo, Mouse, Settings,Key,
+    Direction::{self, Click, Press, Release},
+    Key,
     {Axis::Horizontal, Axis::Vertical},
-    {Coordinate::Abs, Coordinate::Rel},
 };
 use env_logger::{Builder, Env};
 use image::imageops::flip_horizontal;
 
-use device_query::{DeviceQuery, DeviceState, Keycode};
-
-fn get_backing_scale_factor() -> f32 {
-    unsafe {
-        // 获取 NSScreen 类
-        let ns_screen_class: *const Class = Class::get("NSScreen").expect("Failed to get NSScreen class");
-
-        // 获取主屏幕对象
-        let main_screen: *mut Object = msg_send![ns_screen_class, mainScreen];
-        if main_screen.is_null() {
-            panic!("Failed to get main screen");
-        }
-
-        // 获取 backingScaleFactor
-        let scale_factor: f64 = msg_send![main_screen, backingScaleFactor];
-        scale_factor as f32
-    }
-}
-
-
-/// 计算两个图像区域之间的归一化互相关 (Normalized Cross-Correlation, NCC)
-fn normalized_cross_correlation(
-    img_region: &[u8],
-    template: &[u8],
-    template_size: usize,
-    template_mean: f32,
-    template_stddev: f32,
-) -> f32 {
-
-    // 计算图像区域的均值和标准差
-    let img_mean = img_region.iter().map(|&x| x as u32).sum::<u32>() as f32 / template_size as f32;
-    let img_variance = img_region
-        .iter()
-        .map(|&x| (x as f32 - img_mean).powi(2))
-        .sum::<f32>() / template_size as f32;
-    let img_stddev = img_variance.sqrt();
-
-    // 如果标准差为0，避免除以0
-    if img_stddev == 0.0 || template_stddev == 0.0 {
-        return 0.0;
-    }
-
-    // 计算归一化互相关
-    let mut ncc_sum = 0.0;
-    for i in 0..template_size {
-        ncc_sum += ((img_region[i] as f32 - img_mean) * (template[i] as f32 - template_mean)) / (img_stddev * template_stddev);
-    }
-    ncc_sum / template_size as f32
-}
-
-/// 模板匹配函数，返回最佳匹配位置的坐标
-fn match_template(img: &DynamicImage, template: &DynamicImage, best_ncc: f32) -> Option<(u32, u32, f32)> {
-    // 将图像转换为灰度图
-    let img_gray = img.to_luma8();
-    let template_gray = template.to_luma8();
-
-    // 获取图像尺寸
-    let (img_width, img_height) = img_gray.dimensions();
-    let (template_width, template_height) = template_gray.dimensions();
-
-    // 确保模板尺寸不大于图像尺寸
-    if template_width > img_width || template_height > img_height {
-        println!("模板尺寸不能大于图像尺寸！");
-        return None;
-    }
-
-    // 将模板图像数据转换为向量
-    let template_data: Vec<u8> = template_gray.into_raw();
-    let template_size = (template_width * template_height) as usize;
+use device_query::Keycode;
 
-    // 计算模板的均值和标准差
-    let template_mean = template_data.iter().map(|&x| x as u32).sum::<u32>() as f32 / template_size as f32;
-    let template_variance = template_data
-        .iter()
-        .map(|&x| (x as f32 - template_mean).powi(2))
-        .sum::<f32>() / template_size as f32;
-    let template_stddev = template_variance.sqrt();
-
-    // 使用 Arc 和 Mutex 来共享和同步最大相关系数和最佳匹配位置
-    let max_ncc = Arc::new(Mutex::new(f32::NEG_INFINITY));
-    let best_match_pos = Arc::new(Mutex::new((0, 0, 0.0)));
-
-    // 提前退出标志
-    let stop_flag = Arc::new(AtomicBool::new(false));
-
-    // 使用 rayon 的 par_iter 并行化外层的 y 循环
-    (0..(img_height - template_height + 1)).into_par_iter().for_each(|y| {
-        if stop_flag.load(Ordering::Relaxed) {
-            return;
-        }
-        for x in 0..(img_width - template_width + 1) {
-            // 使用 sub_image 创建一个子图像视图
-            let img_sub_image = img_gray.view(x, y, template_width, template_height);
-
-            // 将子图像视图转换为新的 ImageBuffer
-            let img_region: ImageBuffer<Luma<u8>, Vec<u8>> = img_sub_image.to_image();
-
-            // 提取当前窗口的图像区域数据
-            let img_region_data: Vec<u8> = img_region.into_raw();
-
-            // 计算归一化互相关
-            let ncc = normalized_cross_correlation(&img_region_data, &template_data, template_size, template_mean, template_stddev);
-
-            // 更新最大相关系数和最佳匹配位置
-            let mut max_ncc_lock = max_ncc.lock().unwrap();
-            let mut best_match_pos_lock = best_match_pos.lock().unwrap();
-            if ncc > *max_ncc_lock {
-                *max_ncc_lock = ncc;
-                *best_match_pos_lock = (x, y, ncc);
-                // 如果找到非常高的 NCC 值，设置提前退出标志
-                if ncc > best_ncc {
-                    stop_flag.store(true, Ordering::Relaxed);
-                }
-            }
-        }
-    });
+use features::{find_feature, FeatureOptions};
+use matcher::{alpha_mask, mask_from_image, match_prepared, match_prepared_all, rotate_template, ColorMode, MaskSource, MatchOptions, SearchPolicy, Template};
+use motion::{Curve, MotionOptions};
+use preprocess::Step;
+use platform::WindowInfo;
+use window_query::{Pick, WindowQuery};
 
-    // 返回最佳匹配位置
-    let result = Some(*best_match_pos.lock().unwrap()); result
-}
 
 // 定义一个用于处理 console.log 的 Rust 函数
 fn js_console_log(_this: &JsValue, args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
@@ -185,6 +83,7 @@ fn js_console_error(_this: &JsValue, args: &[JsValue], _context: &mut Context) -
 
 
 static mut FIND_WINDOW_TITLE: String = String::new();
+static mut FIND_WINDOW_ID: Option<u32> = None;
 static mut FIND_TEMPLATE_X: u32 = 0;
 static mut FIND_TEMPLATE_Y: u32 = 0;
 static mut FIND_TEMPLATE_NCC: f32 = 0.0;
@@ -195,314 +94,2099 @@ static mut LAST_WINDOW_HEIGHT: u32 = 0;
 static mut LAST_TEMPLATE_WIDTH: u32 = 0;
 static mut LAST_TEMPLATE_HEIGHT: u32 = 0;
 
+/// 全局匹配选项，由 setMatchOptions 修改，findTemplate 的选项参数在此基础上覆盖
+static MATCH_OPTIONS: Lazy<Mutex<MatchOptions>> = Lazy::new(|| Mutex::new(MatchOptions::default()));
+/// 全局鼠标移动与点击选项，由 setMouseOptions 修改
+static MOUSE_OPTIONS: Lazy<Mutex<MotionOptions>> = Lazy::new(|| Mutex::new(MotionOptions::default()));
+
+thread_local! {
+    /// onHotkey 注册的回调，JavaScript 对象只能留在脚本线程
+    static HOTKEY_CALLBACKS: RefCell<HashMap<u32, JsObject>> = RefCell::new(HashMap::new());
+    /// onPause / onResume 注册的回调
+    static PAUSE_HOOKS: RefCell<Vec<JsObject>> = const { RefCell::new(Vec::new()) };
+    static RESUME_HOOKS: RefCell<Vec<JsObject>> = const { RefCell::new(Vec::new()) };
+    /// 正在执行回调，回调里的 sleep 等不再嵌套执行回调
+    static RUNNING_CALLBACKS: Cell<bool> = const { Cell::new(false) };
+}
+// 多尺度、旋转匹配时每个模板上次匹配成功的 (缩放比例, 角度)
+static TEMPLATE_TRANSFORMS: Lazy<Mutex<HashMap<String, (f32, f32)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// 每个窗口中每个模板上次匹配成功的位置 (x, y, 宽, 高)，按 (窗口 id, 模板文件) 区分，跟踪模式先在其附近搜索
+static TEMPLATE_POSITIONS: Lazy<Mutex<HashMap<(u32, String), (u32, u32, u32, u32)>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+// snapshot() 保留的最近几帧截图，快照对象通过 frameId 引用
+type FrameStore = VecDeque<(u32, Arc<Frame>)>;
+static FRAMES: Lazy<Mutex<FrameStore>> = Lazy::new(|| Mutex::new(VecDeque::new()));
+static NEXT_FRAME_ID: AtomicU32 = AtomicU32::new(1);
+/// 最多保留的快照数，更早的快照被释放
+const MAX_FRAMES: usize = 8;
+// 最近一次截图，getPixel 直接读取其中的像素
+static LAST_FRAME: Lazy<Mutex<Option<Arc<Frame>>>> = Lazy::new(|| Mutex::new(None));
+/// getPixel 复用最近一次截图的最长时间（毫秒），超过后重新截图，轮询像素的循环仍能看到变化
+const PIXEL_FRAME_MAX_AGE_MS: f64 = 200.0;
+
+
+fn find_window(query: &WindowQuery) -> Option<WindowInfo> {
+    let window = query.select(platform::current().windows())?;
+    info!("找到窗口 [id={} pid={} app={:?}]: {:?}", window.id, window.pid, window.app_name, window.title);
+    unsafe {
+        FIND_WINDOW_TITLE = window.title.clone();
+        FIND_WINDOW_ID = Some(window.id);
+        LAST_WINDOW_WIDTH = window.width;
+        LAST_WINDOW_HEIGHT = window.height;
+    }
+    Some(window)
+}
+
+/// 查找 findWindow 选中的目标窗口，并刷新窗口尺寸。
+/// 优先按窗口 id 查找，标题变化也不影响；窗口重新创建后 id 失效时再按原标题查找
+fn target_window() -> Option<WindowInfo> {
+    let windows = platform::current().windows();
+    let (id, title) = unsafe { (FIND_WINDOW_ID, FIND_WINDOW_TITLE.clone()) };
+    let window = windows
+        .iter()
+        .find(|window| Some(window.id) == id)
+        .or_else(|| windows.iter().find(|window| window.title == title))?
+        .clone();
+    unsafe {
+        FIND_WINDOW_ID = Some(window.id);
+        LAST_WINDOW_WIDTH = window.width;
+        LAST_WINDOW_HEIGHT = window.height;
+    }
+    Some(window)
+}
+
+/// 读取 findWindow/findWindows 的参数：字符串为精确标题，对象为查找条件
+/// `{title, titleContains, titleRegex, appName, pid, id, pick: "first" | "largest" | "frontmost"}`
+fn parse_window_query(value: &JsValue, context: &mut Context) -> JsResult<Option<WindowQuery>> {
+    if let Some(title) = value.as_string() {
+        return Ok(Some(WindowQuery::by_title(title.to_string())));
+    }
+    let Some(object) = value.as_object() else {
+        return Ok(None);
+    };
+    let mut query = WindowQuery::default();
 
-fn find_window(title:String) -> bool {
-    let windows = Window::all().unwrap();
-    let mut result = false;
-    for window in windows {
-        if window.title().to_string() == title {
-            unsafe {
-                FIND_WINDOW_TITLE = window.title().to_string();
-                LAST_WINDOW_WIDTH = window.width();
-                LAST_WINDOW_HEIGHT = window.height();
+    let string_field = |name: &str, context: &mut Context| -> JsResult<Option<String>> {
+        let value = object.get(name, context)?;
+        if value.is_null_or_undefined() {
+            return Ok(None);
+        }
+        Ok(Some(value.to_string(context)?.to_string()))
+    };
+    query.title = string_field("title", context)?;
+    query.title_contains = string_field("titleContains", context)?;
+    query.app_name = string_field("appName", context)?;
+
+    // titleRegex 可以是字符串，也可以是 /.../ 正则对象（取其 source 与 flags）
+    let regex = object.get("titleRegex", context)?;
+    if !regex.is_null_or_undefined() {
+        let (source, flags) = match regex.as_object() {
+            Some(regex_object) if regex_object.is_regexp() => {
+                let flags = regex_object.get("flags", context)?.to_string(context)?.to_string();
+                (regex_object.get("source", context)?, flags)
             }
-            result = true;
-            break;
+            _ => (regex.clone(), String::new()),
+        };
+        let pattern = source.to_string(context)?.to_string();
+        match window_query::title_regex(&pattern, &flags) {
+            Ok(regex) => query.title_regex = Some(regex),
+            Err(e) => return context.throw_syntax_error(format!("titleRegex 无效: {}", e)).map(|_| None),
+        }
+    }
+
+    let pid = object.get("pid", context)?;
+    if !pid.is_null_or_undefined() {
+        query.pid = Some(pid.to_u32(context)?);
+    }
+    let id = object.get("id", context)?;
+    if !id.is_null_or_undefined() {
+        query.id = Some(id.to_u32(context)?);
+    }
+    let pick = object.get("pick", context)?;
+    if !pick.is_null_or_undefined() {
+        let name = pick.to_string(context)?.to_string();
+        match Pick::parse(&name) {
+            Some(pick) => query.pick = pick,
+            None => return context.throw_range_error(format!("pick 只能是 first、largest 或 frontmost: {}", name)).map(|_| None),
+        }
+    }
+    Ok(Some(query))
+}
+
+/// 按 id 查找窗口，窗口句柄用它保持绑定同一个窗口
+fn window_by_id(id: u32) -> Option<WindowInfo> {
+    platform::current().windows().into_iter().find(|window| window.id == id)
+}
+
+/// 把窗口转换为 JavaScript 窗口句柄，句柄的方法都通过 this.id 找到对应窗口
+fn window_object(context: &mut Context, window: &WindowInfo) -> JsValue {
+    ObjectInitializer::new(context)
+        .property("id", window.id, Attribute::all())
+        .property("pid", window.pid, Attribute::all())
+        .property("title", window.title.as_str(), Attribute::all())
+        .property("appName", window.app_name.as_str(), Attribute::all())
+        .function(js_window_find_template, "findTemplate", 1)
+        .function(js_window_find_all_templates, "findAllTemplates", 1)
+        .function(js_window_click, "click", 2)
+        .function(js_window_activate, "activate", 0)
+        .function(js_window_handle_width, "width", 0)
+        .function(js_window_handle_height, "height", 0)
+        .function(js_window_capture, "capture", 1)
+        .function(js_window_snapshot, "snapshot", 0)
+        .build()
+        .into()
+}
+
+/// 取出窗口句柄 this 对应的窗口，窗口已关闭时返回 None
+fn this_window(this: &JsValue, context: &mut Context) -> JsResult<Option<WindowInfo>> {
+    let Some(object) = this.as_object() else {
+        context.throw_type_error("必须在窗口句柄上调用")?;
+        return Ok(None);
+    };
+    let id = object.get("id", context)?.to_u32(context)?;
+    let window = window_by_id(id);
+    if window.is_none() {
+        warn!("窗口 {} 已不存在", id);
+    }
+    Ok(window)
+}
+
+/// 窗口句柄的 findTemplate(file, [x, y, w, h], [options])，返回匹配对象或 null
+fn js_window_find_template(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(window) = this_window(this, context)? else {
+        return Ok(JsValue::null());
+    };
+    let Some(png_file) = args.get(0).and_then(|arg| arg.as_string()).map(|s| s.to_string()) else {
+        return context.throw_type_error("findTemplate 需要模版文件名");
+    };
+    let (region, options_arg) = region_and_options(args);
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(&options_arg, &global_options, context)?;
+    match locate_template(&window, &png_file, region, &options) {
+        Some(found) => Ok(match_object(context, &found)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// 窗口句柄的 findAllTemplates(file, [x, y, w, h], [options])
+fn js_window_find_all_templates(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(window) = this_window(this, context)? else {
+        return js_array(context, Vec::new());
+    };
+    find_all_templates_in(args, context, || capture_frame(&window))
+}
+
+/// 窗口句柄的 click(x, y)，坐标为窗口截图坐标，可以为负（窗口左侧或上方）
+fn js_window_click(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(window) = this_window(this, context)? else {
+        return Ok(JsValue::Boolean(false));
+    };
+    let x = number_arg(args, 0).unwrap_or(0.0) as i32;
+    let y = number_arg(args, 1).unwrap_or(0.0) as i32;
+    Ok(JsValue::Boolean(click_at(&window, x, y)))
+}
+
+fn js_window_activate(this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(window) = this_window(this, context)? else {
+        return Ok(JsValue::Boolean(false));
+    };
+    Ok(JsValue::Boolean(platform::current().activate(&window)))
+}
+
+/// 窗口句柄的 width()，与 windowWidth() 一样返回截图像素
+fn js_window_handle_width(this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let Some(window) = this_window(this, context)? else {
+        return Ok(JsValue::Integer(0));
+    };
+    Ok(JsValue::Integer((window.width as f32 * platform::current().scale_factor()) as i32))
+}
+
+fn js_window_handle_height(this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let Some(window) = this_window(this, context)? else {
+        return Ok(JsValue::Integer(0));
+    };
+    Ok(JsValue::Integer((window.height as f32 * platform::current().scale_factor()) as i32))
+}
+
+/// 窗口句柄的 capture(file)，把当前截图保存到文件
+fn js_window_capture(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(window) = this_window(this, context)? else {
+        return Ok(JsValue::Boolean(false));
+    };
+    let Some(file) = args.get(0).and_then(|arg| arg.as_string()).map(|s| s.to_string()) else {
+        return context.throw_type_error("capture 需要保存的文件名");
+    };
+    let Some((image, _)) = capture_window(&window) else {
+        return Ok(JsValue::Boolean(false));
+    };
+    match image.save(&file) {
+        Ok(()) => Ok(JsValue::Boolean(true)),
+        Err(e) => {
+            error!("保存截图失败，{:?}: {:?}", file, e);
+            Ok(JsValue::Boolean(false))
         }
     }
-    result
 }
 
 // 将 Rust 函数包装为可以在 JavaScript 中调用的形式
 fn js_find_window(_this: &JsValue, args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
-    // 获取第一个参数（假设它是数字）
-    let title = if let Some(arg) = args.get(0) {
-        arg.as_string().map(|n| n)
-    } else {
-        None
-    };
+    // 第一个参数为标题或查找条件
+    let query = parse_window_query(args.get(0).unwrap_or(&JsValue::undefined()), _context)?;
 
-    // 如果没有提供有效的参数，则返回 undefined
-    let result = match title  {
-        Some(n) => find_window(n.to_string()).into(),
+    // 找到时返回窗口句柄，否则返回 false
+    let result = match query.and_then(|query| find_window(&query))  {
+        Some(window) => window_object(_context, &window),
         None => JsValue::Boolean(false),
     };
 
     Ok(result)
 }
 
-fn active_window(pid: u32) -> bool {
-    let app = Class::get("NSRunningApplication").unwrap();
-    let running_app: id  = unsafe { msg_send![app, runningApplicationWithProcessIdentifier: pid] };
-    if running_app != nil {
-        let _: () = unsafe { msg_send![running_app, activateWithOptions: 0] };
-        return true;
+/// findWindows(title | 查找条件)：返回所有满足条件的窗口句柄，用于同时控制多个客户端
+fn js_find_windows(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let Some(query) = parse_window_query(args.get(0).unwrap_or(&JsValue::undefined()), context)? else {
+        return js_array(context, Vec::new());
+    };
+    let handles = query
+        .filter(platform::current().windows())
+        .iter()
+        .map(|window| window_object(context, window))
+        .collect();
+    js_array(context, handles)
+}
+
+fn js_active_window(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(_context)?;
+    if let Some(window) = target_window() {
+        // 激活应用程序
+        return Ok(JsValue::Boolean(platform::current().activate(&window)));
     }
-    false
+    Ok(JsValue::Boolean(false))
 }
 
+/// 一次模板匹配的结果，坐标为窗口截图坐标
+#[derive(Debug, Clone)]
+struct TemplateMatch {
+    /// 匹配所在窗口的 id
+    window_id: u32,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    /// 模板中心的位置，一般是矩形的中心；特征匹配的外接矩形被截图边界裁剪后仍是模板中心
+    center: (u32, u32),
+    score: f32,
+    /// 模板的缩放比例，未使用多尺度匹配时为 1
+    scale: f32,
+    /// 模板的旋转角度（度，顺时针为正），未使用旋转匹配时为 0
+    angle: f32,
+    /// 截图时间，毫秒时间戳
+    timestamp: f64,
+}
 
-fn js_active_window(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
-    let windows = Window::all().unwrap();
-    for window in windows {
-        if unsafe { window.title().to_string() == FIND_WINDOW_TITLE} {
-            unsafe {
-                LAST_WINDOW_WIDTH = window.width();
-                LAST_WINDOW_HEIGHT = window.height();
-            }
-            // 激活应用程序
-            return Ok(JsValue::Boolean(active_window(window.pid())));
+/// 截取目标窗口，窗口最小化时先激活，返回截图与截图时间（毫秒时间戳）
+fn capture_window(window: &WindowInfo) -> Option<(RgbaImage, f64)> {
+    if window.is_minimized {
+        info!("窗口当前是最小化状态，自动激活窗口。");
+        platform::current().activate(window);
+    }
+    let timestamp = chrono::Local::now().timestamp_millis() as f64;
+    platform::current().capture(window).map(|image| (image, timestamp))
+}
+
+/// 一次截图，同一帧上的多次查找共用同样的像素
+struct Frame {
+    window_id: u32,
+    image: RgbaImage,
+    /// 截图时间，毫秒时间戳
+    timestamp: f64,
+}
+
+impl Frame {
+    /// 裁剪出搜索区域 (x, y, w, h)，未指定区域时使用整张截图
+    fn region(&self, region: Option<(u32, u32, u32, u32)>) -> Option<(DynamicImage, u32, u32)> {
+        let image = &self.image;
+        let Some((offset_x, offset_y, width, height)) = region else {
+            return Some((DynamicImage::from(image.clone()), 0, 0));
+        };
+        if (offset_x+width)>image.width() || (offset_y+height)>image.height() {
+            error!("设置的搜索区域超过窗口区域，{}+{}<{}, {}+{}<{}", offset_x, width, image.width(), offset_y, height, image.height());
+            return None;
+        }
+        let sub_image = image.view(offset_x, offset_y, width, height);
+        Some((DynamicImage::from(sub_image.to_image()), offset_x, offset_y))
+    }
+}
+
+/// 截取窗口，截图同时记为最近一次截图
+fn capture_frame(window: &WindowInfo) -> Option<Arc<Frame>> {
+    let (image, timestamp) = capture_window(window)?;
+    let frame = Arc::new(Frame { window_id: window.id, image, timestamp });
+    *LAST_FRAME.lock().unwrap() = Some(frame.clone());
+    Some(frame)
+}
+
+/// 窗口最近一次的截图，没有或已超过 PIXEL_FRAME_MAX_AGE_MS 时重新截取
+fn recent_frame(window: &WindowInfo) -> Option<Arc<Frame>> {
+    let now = chrono::Local::now().timestamp_millis() as f64;
+    let last = LAST_FRAME.lock().unwrap().clone();
+    match last {
+        Some(frame) if frame.window_id == window.id && now - frame.timestamp <= PIXEL_FRAME_MAX_AGE_MS => Some(frame),
+        _ => capture_frame(window),
+    }
+}
+
+/// 截取窗口并裁剪出搜索区域 (x, y, w, h)，未指定区域时使用整个窗口
+fn capture_region(window: &WindowInfo, region: Option<(u32, u32, u32, u32)>) -> Option<(DynamicImage, u32, u32, f64)> {
+    let frame = capture_frame(window)?;
+    let (image, offset_x, offset_y) = frame.region(region)?;
+    Some((image, offset_x, offset_y, frame.timestamp))
+}
+
+/// 在窗口中查找模板，region 为 (x, y, w, h) 搜索区域
+fn locate_template(window: &WindowInfo, template_file: &str, region: Option<(u32, u32, u32, u32)>, options: &MatchOptions) -> Option<TemplateMatch> {
+    let frame = capture_frame(window)?;
+    locate_in_frame(&frame, template_file, region, options)
+}
+
+/// 在一帧截图中查找模板，出错时记录日志并返回 None
+fn locate_in_frame(frame: &Frame, template_file: &str, region: Option<(u32, u32, u32, u32)>, options: &MatchOptions) -> Option<TemplateMatch> {
+    match try_locate_in_frame(frame, template_file, region, options) {
+        Ok(found) => found,
+        Err(e) => {
+            error!("{}", e);
+            None
         }
     }
-    Ok(JsValue::Boolean(false))
 }
 
-/// 调整图像对比度
-fn adjust_contrast(image: &DynamicImage, factor: f32) -> DynamicImage {
-    let (width, height) = image.dimensions();
-    let mut out_buffer = ImageBuffer::new(width, height);
+/// 在一帧截图中查找模板：模板读取失败、搜索区域无效时返回 Err，查找完成但没有结果时返回 Ok(None)
+fn try_locate_in_frame(frame: &Frame, template_file: &str, region: Option<(u32, u32, u32, u32)>, options: &MatchOptions) -> Result<Option<TemplateMatch>, String> {
+    let Some(template_image) = templates::load(template_file) else {
+        return Err(format!("无法读取模版文件: {}", template_file));
+    };
+    if let Some((offset_x, offset_y, width, height)) = region {
+        info!("搜索区域: ({},{})-({},{})", offset_x, offset_y, offset_x+width, offset_y+height);
+        if options.scale_range.is_none() && options.angle_range.is_none() && (width<template_image.width() || height<template_image.height()) {
+            return Err(format!("设置的搜索区域小于模版宽度，{}<{}, {}<{}", width, template_image.width(), height, template_image.height()));
+        }
+    }
+    info!("模版图片尺寸: ({}, {}), {}", template_image.width(), template_image.height(), template_file);
+    if options.track {
+        if let Some(found) = track_in_frame(frame, template_file, &template_image, region, options) {
+            return Ok(Some(found));
+        }
+    }
+    let Some((dynamic_img, offset_x, offset_y)) = frame.region(region) else {
+        return Err(format!("搜索区域超出窗口: {:?}", region));
+    };
+    let found = match_in_image(frame, template_file, &template_image, &dynamic_img, (offset_x, offset_y), options);
+    if let Some(found) = &found {
+        remember_position(template_file, found, options);
+    }
+    Ok(found)
+}
 
-    for y in 0..height {
-        for x in 0..width {
-            let pixel = image.get_pixel(x, y);
-            let new_pixel = apply_contrast_to_pixel(pixel, factor);
-            out_buffer.put_pixel(x, y, new_pixel);
+/// 跟踪模式：在模板上次匹配位置四周 track_margin 像素内搜索，得分达到 track_threshold 时返回
+fn track_in_frame(frame: &Frame, template_file: &str, template_image: &Arc<DynamicImage>, region: Option<(u32, u32, u32, u32)>, options: &MatchOptions) -> Option<TemplateMatch> {
+    let (last_x, last_y, last_width, last_height) = TEMPLATE_POSITIONS.lock().unwrap().get(&(frame.window_id, template_file.to_string())).copied()?;
+    // 局部区域不超出搜索区域
+    let (region_x, region_y, region_width, region_height) = region.unwrap_or((0, 0, frame.image.width(), frame.image.height()));
+    let margin = options.track_margin;
+    let left = last_x.saturating_sub(margin).max(region_x);
+    let top = last_y.saturating_sub(margin).max(region_y);
+    let right = last_x.saturating_add(last_width).saturating_add(margin).min(region_x.saturating_add(region_width)).min(frame.image.width());
+    let bottom = last_y.saturating_add(last_height).saturating_add(margin).min(region_y.saturating_add(region_height)).min(frame.image.height());
+    if right < left + last_width || bottom < top + last_height {
+        return None;
+    }
+    let (local_img, offset_x, offset_y) = frame.region(Some((left, top, right - left, bottom - top)))?;
+    let found = match_in_image(frame, template_file, template_image, &local_img, (offset_x, offset_y), options);
+    match found {
+        Some(found) if found.score >= options.track_threshold => {
+            debug!("跟踪模式: 在上次位置附近找到, ({},{})-({},{}), {}", left, top, right, bottom, template_file);
+            remember_position(template_file, &found, options);
+            Some(found)
+        }
+        _ => {
+            info!("跟踪模式: 上次位置附近未找到，搜索整个区域, {}", template_file);
+            None
         }
     }
+}
 
-    DynamicImage::ImageRgba8(out_buffer)
+/// 得分达到跟踪阈值的匹配位置记录下来，供跟踪模式使用
+fn remember_position(template_file: &str, found: &TemplateMatch, options: &MatchOptions) {
+    if found.score >= options.track_threshold {
+        TEMPLATE_POSITIONS.lock().unwrap().insert((found.window_id, template_file.to_string()), (found.x, found.y, found.width, found.height));
+    }
 }
 
-/// 对单个像素应用对比度调整
-fn apply_contrast_to_pixel(pixel: Rgba<u8>, factor: f32) -> Rgba<u8> {
-    let f = |c: u8| -> u8 {
-        let c_f32 = c as f32 / 255.0;
-        let adjusted = (128.0 - (128.0 * factor) + (c_f32 * factor * 255.0)) as i32;
-        let clamped = adjusted.clamp(0, 255);
-        clamped as u8
+/// 在截图 (或其中一块区域) 中匹配模板，offset 为该区域在截图中的左上角
+fn match_in_image(frame: &Frame, template_file: &str, template_image: &Arc<DynamicImage>, img: &DynamicImage, (offset_x, offset_y): (u32, u32), options: &MatchOptions) -> Option<TemplateMatch> {
+    let dynamic_img = preprocess::apply(img, &options.preprocess);
+    let start = Instant::now();
+    let match_result = match_best_transform(template_file, &dynamic_img, template_image, options);
+    info!("匹配结果: {:?}, 耗时: {:?}", match_result, start.elapsed());
+    let Some(TransformedMatch { x, y, score: ncc, scale, angle, width, height }) = match_result else {
+        info!("未找到符合条件的坐标");
+        return None;
     };
+    info!("符合条件的坐标: ({}, {}), ncc: {}, 缩放: {}, 角度: {}", offset_x+x, offset_y+y, ncc, scale, angle);
+    Some(TemplateMatch {
+        window_id: frame.window_id,
+        x: offset_x + x,
+        y: offset_y + y,
+        width,
+        height,
+        center: (offset_x + x + width / 2, offset_y + y + height / 2),
+        score: ncc,
+        scale,
+        angle,
+        timestamp: frame.timestamp,
+    })
+}
 
-    Rgba([f(pixel[0]), f(pixel[1]), f(pixel[2]), pixel[3]])
+/// 某个缩放比例、角度下的最佳匹配，width/height 为变换后的模板尺寸
+#[derive(Debug, Clone, Copy)]
+struct TransformedMatch {
+    x: u32,
+    y: u32,
+    score: f32,
+    scale: f32,
+    angle: f32,
+    width: u32,
+    height: u32,
 }
 
-fn find_template(template_file:String) -> f32 {
-    let windows = Window::all().unwrap();
-    let mut result = 0.0;
-    for window in windows {
-        if unsafe { window.title().to_string() == FIND_WINDOW_TITLE} {
-            if window.is_minimized() {
-                info!("窗口当前是最小化状态，自动激活窗口。");
-                active_window(window.pid());
-            }
-            unsafe {
-                LAST_WINDOW_WIDTH = window.width();
-                LAST_WINDOW_HEIGHT = window.height();
-            }
-            if let Ok(template_image) = image::open(template_file.clone()) {
-                info!("模版图片尺寸: ({}, {}), {}", template_image.width(), template_image.height(), template_file);
-                let image = window.capture_image().unwrap();
-                let dynamic_img: DynamicImage = DynamicImage::from(image);
-                adjust_contrast(&dynamic_img, 1.5);
-                //dynamic_img.save("dbd.png").unwrap();
-                adjust_contrast(&template_image, 1.5);
-                let start = Instant::now();
-                let match_result = match_template(&dynamic_img, &template_image, 0.95);
-                info!("匹配结果: {:?}, 耗时: {:?}", match_result, start.elapsed());
-                if let Some((x, y, ncc)) = match_result {
-                    info!("符合条件的坐标: ({}, {}), ncc: {}", x, y, ncc);
-                    unsafe {
-                        FIND_TEMPLATE_X = x;
-                        FIND_TEMPLATE_Y = y;
-                        FIND_TEMPLATE_NCC = ncc;
-                        LAST_TEMPLATE_WIDTH = template_image.width();
-                        LAST_TEMPLATE_HEIGHT = template_image.height();
-                    }
-                    result = ncc;
-                } else {
-                    info!("未找到符合条件的坐标");
-                }
-            } else {
-                error!("打开模版文件失败，{:?}", template_file);
-            }
-            break;
+/// 多尺度、旋转匹配时，缓存的变换得分不低于该值就直接使用，最佳结果得分不低于该值才写入缓存
+const TRANSFORM_CACHE_MIN_NCC: f32 = 0.8;
+
+/// 按比例缩放、按角度旋转模板与掩码，再做预处理；缩放后为空时返回 None
+fn transform_template(template_image: &DynamicImage, mask: Option<&GrayImage>, scale: f32, angle: f32, options: &MatchOptions) -> Option<(DynamicImage, Option<GrayImage>)> {
+    let (mut template, mut mask) = (template_image.clone(), mask.cloned());
+    if scale != 1.0 {
+        let width = (template_image.width() as f32 * scale).round() as u32;
+        let height = (template_image.height() as f32 * scale).round() as u32;
+        if width == 0 || height == 0 {
+            return None;
         }
+        template = template.resize_exact(width, height, FilterType::Triangle);
+        mask = mask.map(|mask| image::imageops::resize(&mask, width, height, FilterType::Triangle));
     }
-    result
+    if angle != 0.0 {
+        let (rotated, rotated_mask) = rotate_template(&template, mask.as_ref(), angle);
+        template = rotated;
+        mask = Some(rotated_mask);
+    }
+    Some((preprocess::apply(&template, &options.preprocess), mask))
+}
+
+/// 取得按选项变换、预处理并算好统计量的模板，按模板文件、选项与变换缓存
+fn prepare_template(template_file: &str, template_image: &Arc<DynamicImage>, scale: f32, angle: f32, options: &MatchOptions) -> Option<Arc<Template>> {
+    // 只有影响模板本身的选项进入缓存键；掩码文件被修改时键随之变化
+    let mask_modified = match &options.mask {
+        MaskSource::File(file) => templates::modified(file),
+        _ => None,
+    };
+    let key = format!("{:?}|{:?}|{:?}|{:?}|{}|{}", options.preprocess, options.mask, mask_modified, options.color, scale, angle);
+    templates::prepared(template_file, template_image, &key, || {
+        let mask = template_mask(template_image, options)?;
+        let (template, mask) = transform_template(template_image, mask.as_ref(), scale, angle, options)?;
+        Template::new(&template, mask.as_ref(), options.color)
+    })
+}
+
+/// 在指定缩放比例、角度下匹配，img 为已预处理的截图
+fn match_transformed(template_file: &str, img: &DynamicImage, template_image: &Arc<DynamicImage>, (scale, angle): (f32, f32), options: &MatchOptions) -> Option<TransformedMatch> {
+    let template = prepare_template(template_file, template_image, scale, angle, options)?;
+    if template.width() > img.width() || template.height() > img.height() {
+        return None;
+    }
+    let (x, y, score) = match_prepared(img, &template, options)?;
+    Some(TransformedMatch { x, y, score, scale, angle, width: template.width(), height: template.height() })
+}
+
+/// 保留三位小数，避免步长累加的浮点误差
+fn round_step(value: f32) -> f32 {
+    (value * 1000.0).round() / 1000.0
+}
+
+/// 按步长覆盖范围 [min, max] 的取值，未设置范围时只有 default
+fn range_steps(range: Option<(f32, f32)>, step: f32, default: f32) -> Vec<f32> {
+    let Some((min, max)) = range else {
+        return vec![default];
+    };
+    let count = ((max - min) / step).floor() as u32;
+    let mut values: Vec<f32> = (0..=count).map(|i| round_step(min + step * i as f32)).collect();
+    if values.last().map_or(true, |&last| max - last > step * 0.01) {
+        values.push(max);
+    }
+    values
 }
 
-fn find_template_cut(template_file:String, offset_x: u32, offset_y: u32, width: u32, height: u32) -> f32 {
-    let windows = Window::all().unwrap();
-    let mut result = 0.0;
-    for window in windows {
-        if unsafe { window.title().to_string() == FIND_WINDOW_TITLE} {
-            if window.is_minimized() {
-                info!("窗口当前是最小化状态，自动激活窗口。");
-                active_window(window.pid());
+fn in_range(value: f32, range: Option<(f32, f32)>, default: f32) -> bool {
+    match range {
+        Some((min, max)) => value >= min && value <= max,
+        None => value == default,
+    }
+}
+
+/// 匹配模板，设置了缩放或角度范围时在范围内搜索最佳的 (缩放比例, 角度)。
+/// 先尝试该模板上次成功的变换，不够好时再按步长搜索整个范围，并在最佳变换附近细化
+fn match_best_transform(template_file: &str, img: &DynamicImage, template_image: &Arc<DynamicImage>, options: &MatchOptions) -> Option<TransformedMatch> {
+    if options.scale_range.is_none() && options.angle_range.is_none() {
+        return match_transformed(template_file, img, template_image, (1.0, 0.0), options);
+    }
+    let cached = TEMPLATE_TRANSFORMS.lock().unwrap().get(template_file).copied();
+    if let Some((scale, angle)) = cached.filter(|&(scale, angle)| in_range(scale, options.scale_range, 1.0) && in_range(angle, options.angle_range, 0.0)) {
+        if let Some(found) = match_transformed(template_file, img, template_image, (scale, angle), options) {
+            if found.score >= TRANSFORM_CACHE_MIN_NCC {
+                debug!("使用缓存的缩放比例: {}, 角度: {}, {}", scale, angle, template_file);
+                return Some(found);
             }
-            unsafe {
-                LAST_WINDOW_WIDTH = window.width();
-                LAST_WINDOW_HEIGHT = window.height();
+        }
+    }
+
+    let scale_step = options.scale_step.max(0.01);
+    let angle_step = options.angle_step.max(0.1);
+    let better = |best: Option<TransformedMatch>, found: Option<TransformedMatch>| match (best, found) {
+        (Some(b), Some(f)) if f.score > b.score => Some(f),
+        (None, found) => found,
+        (best, _) => best,
+    };
+    let mut best = None;
+    for scale in range_steps(options.scale_range, scale_step, 1.0) {
+        for angle in range_steps(options.angle_range, angle_step, 0.0) {
+            best = better(best, match_transformed(template_file, img, template_image, (scale, angle), options));
+        }
+    }
+    // 在最佳变换两侧各半个步长内细化，缩放比例与角度分别细化
+    if let Some(coarse) = best {
+        for k in [-2.0, -1.0, 1.0, 2.0] {
+            let scale = round_step(coarse.scale + scale_step * k / 4.0);
+            if options.scale_range.is_some() && in_range(scale, options.scale_range, 1.0) {
+                best = better(best, match_transformed(template_file, img, template_image, (scale, coarse.angle), options));
             }
-            if let Ok(template_image) = image::open(template_file.clone()) {
-                info!("搜索区域: ({},{})-({},{})", offset_x, offset_y, offset_x+width, offset_y+height);
-                info!("模版图片尺寸: ({}, {}), {}", template_image.width(), template_image.height(), template_file);
-                if width<template_image.width() || height<template_image.height() {
-                    error!("设置的搜索区域小于模版宽度，{}<{}, {}<{}", width, template_image.width(), height, template_image.height());
-                    break;
-                }
-                let image = window.capture_image().unwrap();
-                if (offset_x+width)>image.width() || (offset_y+height)>image.height() {
-                    error!("设置的搜索区域超过窗口区域，{}+{}<{}, {}+{}<{}", offset_x, width, image.width(), offset_y, height, image.height());
-                    break;
-                }
-                let sub_image = image.view(offset_x, offset_y, width, height);
-                let dynamic_img: DynamicImage = DynamicImage::from(sub_image.to_image());
-                dynamic_img.save(  format!("{}_cut_{}_{}.png",  template_file , offset_y, offset_y)).unwrap();
-                adjust_contrast(&dynamic_img, 1.5);
-                adjust_contrast(&template_image, 1.5);
-                let start = Instant::now();
-                let match_result = match_template(&dynamic_img, &template_image, 0.95);
-                info!("匹配结果: {:?}, 耗时: {:?}", match_result, start.elapsed());
-                if let Some((x, y, ncc)) = match_result {
-                    info!("符合条件的坐标: ({}, {}), ncc: {}", offset_x+x, offset_y+y, ncc);
-                    unsafe {
-                        FIND_TEMPLATE_X = offset_x + x;
-                        FIND_TEMPLATE_Y = offset_y + y;
-                        FIND_TEMPLATE_NCC = ncc;
-                        LAST_TEMPLATE_WIDTH = template_image.width();
-                        LAST_TEMPLATE_HEIGHT = template_image.height();
-                    }
-                    result = ncc;
-                } else {
-                    info!("未找到符合条件的坐标");
-                }
-            } else {
-                error!("打开模版文件失败，{:?}", template_file);
+        }
+        let refined_scale = best.map_or(coarse.scale, |b| b.scale);
+        for k in [-2.0, -1.0, 1.0, 2.0] {
+            let angle = round_step(coarse.angle + angle_step * k / 4.0);
+            if options.angle_range.is_some() && in_range(angle, options.angle_range, 0.0) {
+                best = better(best, match_transformed(template_file, img, template_image, (refined_scale, angle), options));
             }
-            break;
         }
     }
-    result
+    if let Some(found) = best {
+        info!("最佳缩放比例: {}, 角度: {}, ncc: {}, {}", found.scale, found.angle, found.score, template_file);
+        if found.score >= TRANSFORM_CACHE_MIN_NCC {
+            TEMPLATE_TRANSFORMS.lock().unwrap().insert(template_file.to_string(), (found.scale, found.angle));
+        }
+    }
+    best
 }
 
+/// 按选项取得模板掩码，需要在预处理之前调用（预处理会丢弃 alpha 通道）。
+/// 外层 None 表示掩码图读取失败，内层 None 表示不使用掩码
+fn template_mask(template_image: &DynamicImage, options: &MatchOptions) -> Option<Option<GrayImage>> {
+    match &options.mask {
+        MaskSource::Alpha => Some(alpha_mask(template_image)),
+        MaskSource::File(file) => templates::load(file).map(|mask| Some(mask_from_image(&mask))),
+        MaskSource::Off => Some(None),
+    }
+}
 
-fn js_find_template(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
-    // 获取第一个参数（假设它是数字）
-    let png_file = if let Some(arg) = _args.get(0) {
-        arg.as_string().map(|n| n)
-    } else {
-        None
+/// 记录到 findX/findY/click() 使用的全局状态，返回匹配率
+fn remember_match(found: Option<TemplateMatch>) -> f32 {
+    let Some(found) = found else {
+        return 0.0;
     };
+    unsafe {
+        FIND_TEMPLATE_X = found.x;
+        FIND_TEMPLATE_Y = found.y;
+        FIND_TEMPLATE_NCC = found.score;
+        LAST_TEMPLATE_WIDTH = found.width;
+        LAST_TEMPLATE_HEIGHT = found.height;
+    }
+    found.score
+}
 
-    let offset_x = if let Some(arg) = _args.get(1) {
-        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
+fn find_template(template_file:String, options: &MatchOptions) -> f32 {
+    let Some(window) = target_window() else {
+        return 0.0;
+    };
+    remember_match(locate_template(&window, &template_file, None, options))
+}
+
+fn find_template_cut(template_file:String, offset_x: u32, offset_y: u32, width: u32, height: u32, options: &MatchOptions) -> f32 {
+    let Some(window) = target_window() else {
+        return 0.0;
+    };
+    remember_match(locate_template(&window, &template_file, Some((offset_x, offset_y, width, height)), options))
+}
+
+
+/// 查找所有匹配，region 为 (x, y, w, h)，返回的坐标已换算回窗口截图坐标
+fn find_all_templates(frame: &Frame, template_file: &str, region: Option<(u32, u32, u32, u32)>, threshold: f32, max_results: usize, options: &MatchOptions) -> Vec<TemplateMatch> {
+    let Some(template_image) = templates::load(template_file) else {
+        return Vec::new();
+    };
+    let Some((dynamic_img, offset_x, offset_y)) = frame.region(region) else {
+        return Vec::new();
+    };
+    let dynamic_img = preprocess::apply(&dynamic_img, &options.preprocess);
+    let start = Instant::now();
+    // 多尺度、旋转时先确定最佳变换，再在该变换下查找所有匹配
+    let (scale, angle) = if options.scale_range.is_some() || options.angle_range.is_some() {
+        match match_best_transform(template_file, &dynamic_img, &template_image, options) {
+            Some(found) => (found.scale, found.angle),
+            None => return Vec::new(),
+        }
     } else {
-        0
+        (1.0, 0.0)
+    };
+    let Some(template) = prepare_template(template_file, &template_image, scale, angle, options) else {
+        return Vec::new();
     };
+    let matches = match_prepared_all(&dynamic_img, &template, threshold, max_results, options);
+    info!("找到 {} 个匹配, 耗时: {:?}, {}", matches.len(), start.elapsed(), template_file);
+    matches
+        .into_iter()
+        .map(|(x, y, ncc)| TemplateMatch {
+            window_id: frame.window_id,
+            x: offset_x + x,
+            y: offset_y + y,
+            width: template.width(),
+            height: template.height(),
+            center: (offset_x + x + template.width() / 2, offset_y + y + template.height() / 2),
+            score: ncc,
+            scale,
+            angle,
+            timestamp: frame.timestamp,
+        })
+        .collect()
+}
 
-    let offset_y = if let Some(arg) = _args.get(2) {
-        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
+/// 把匹配结果转换为 JavaScript 对象，附带 click() 方法点击匹配中心
+fn match_object(context: &mut Context, found: &TemplateMatch) -> JsValue {
+    let (center_x, center_y) = found.center;
+    ObjectInitializer::new(context)
+        .property("x", found.x, Attribute::all())
+        .property("y", found.y, Attribute::all())
+        .property("width", found.width, Attribute::all())
+        .property("height", found.height, Attribute::all())
+        .property("centerX", center_x, Attribute::all())
+        .property("centerY", center_y, Attribute::all())
+        .property("score", found.score as f64, Attribute::all())
+        .property("scale", found.scale as f64, Attribute::all())
+        .property("angle", found.angle as f64, Attribute::all())
+        .property("timestamp", found.timestamp, Attribute::all())
+        .property("windowId", found.window_id, Attribute::all())
+        .function(js_match_click, "click", 0)
+        .build()
+        .into()
+}
+
+/// 匹配对象的 click() 方法：在匹配所在窗口中点击模板中心 (centerX, centerY)，设置了 jitter 时在匹配矩形内随机偏移
+fn js_match_click(this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(object) = this.as_object() else {
+        return context.throw_type_error("click 必须在匹配对象上调用");
+    };
+    let window_id = object.get("windowId", context)?.to_u32(context)?;
+    let x = object.get("x", context)?.to_u32(context)?;
+    let y = object.get("y", context)?.to_u32(context)?;
+    let width = object.get("width", context)?.to_u32(context)?;
+    let height = object.get("height", context)?.to_u32(context)?;
+    let jitter = MOUSE_OPTIONS.lock().unwrap().jitter;
+    let (click_x, click_y) = if jitter > 0.0 {
+        motion::click_point((x, y, width, height), jitter)
     } else {
-        0
+        (object.get("centerX", context)?.to_u32(context)?, object.get("centerY", context)?.to_u32(context)?)
+    };
+    match window_by_id(window_id) {
+        Some(window) => Ok(JsValue::Boolean(click_at(&window, click_x as i32, click_y as i32))),
+        None => Ok(JsValue::Boolean(false)),
+    }
+}
+
+/// 读取 JavaScript 选项对象，在 base 的基础上覆盖其中出现的字段
+fn parse_match_options(value: &JsValue, base: &MatchOptions, context: &mut Context) -> JsResult<MatchOptions> {
+    let mut options = base.clone();
+    let Some(object) = value.as_object() else {
+        return Ok(options);
+    };
+    let pyramid = object.get("pyramid", context)?;
+    if !pyramid.is_undefined() {
+        options.pyramid = pyramid.to_boolean();
+    }
+    let pyramid_levels = object.get("pyramidLevels", context)?;
+    if !pyramid_levels.is_undefined() {
+        options.pyramid_levels = pyramid_levels.to_u32(context)?;
+        if options.pyramid_levels > matcher::PYRAMID_MAX_LEVELS {
+            return Err(context.construct_range_error(format!(
+                "pyramidLevels 不能大于 {}: {}",
+                matcher::PYRAMID_MAX_LEVELS,
+                options.pyramid_levels
+            )));
+        }
+    }
+    let mask = object.get("mask", context)?;
+    if !mask.is_undefined() {
+        options.mask = match mask.as_string() {
+            Some(file) => MaskSource::File(file.to_string()),
+            None if mask.to_boolean() => MaskSource::Alpha,
+            None => MaskSource::Off,
+        };
+    }
+    let color = object.get("color", context)?;
+    if !color.is_undefined() {
+        let name = color.to_string(context)?;
+        options.color = match ColorMode::parse(&name) {
+            Some(mode) => mode,
+            None => return Err(context.construct_range_error(format!("未知的颜色模式: {}", name))),
+        };
+    }
+    let tolerance = object.get("colorTolerance", context)?;
+    if !tolerance.is_undefined() {
+        options.color_tolerance = match tolerance.as_number() {
+            Some(value) if value >= 0.0 => Some(value as f32),
+            _ => None,
+        };
+    }
+    let scales = object.get("scales", context)?;
+    if !scales.is_undefined() {
+        options.scale_range = match scales.as_object() {
+            Some(range) => {
+                let min = range.get(0, context)?.to_number(context)? as f32;
+                let max = range.get(1, context)?.to_number(context)? as f32;
+                if !(min > 0.0 && max >= min) {
+                    return Err(context.construct_range_error(format!("缩放范围无效: [{}, {}]", min, max)));
+                }
+                Some((min, max))
+            }
+            None => None,
+        };
+    }
+    let scale_step = object.get("scaleStep", context)?;
+    if !scale_step.is_undefined() {
+        options.scale_step = scale_step.to_number(context)? as f32;
+    }
+    let angles = object.get("angles", context)?;
+    if !angles.is_undefined() {
+        options.angle_range = match angles.as_object() {
+            Some(range) => {
+                let min = range.get(0, context)?.to_number(context)? as f32;
+                let max = range.get(1, context)?.to_number(context)? as f32;
+                if !(max >= min) {
+                    return Err(context.construct_range_error(format!("角度范围无效: [{}, {}]", min, max)));
+                }
+                Some((min, max))
+            }
+            None => None,
+        };
+    }
+    let angle_step = object.get("angleStep", context)?;
+    if !angle_step.is_undefined() {
+        options.angle_step = angle_step.to_number(context)? as f32;
+    }
+    let steps = object.get("preprocess", context)?;
+    if !steps.is_undefined() {
+        options.preprocess = parse_preprocess(&steps, context)?;
+    }
+    let search = object.get("search", context)?;
+    if !search.is_undefined() {
+        options.search = parse_search(&search, context)?;
+    }
+    let track = object.get("track", context)?;
+    if !track.is_undefined() {
+        options.track = track.to_boolean();
+    }
+    let track_margin = object.get("trackMargin", context)?;
+    if !track_margin.is_undefined() {
+        options.track_margin = track_margin.to_u32(context)?;
+    }
+    let track_threshold = object.get("trackThreshold", context)?;
+    if !track_threshold.is_undefined() {
+        options.track_threshold = track_threshold.to_number(context)? as f32;
+    }
+    Ok(options)
+}
+
+/// 读取结果选取方式："best"、"first" 或 {first: 阈值}
+fn parse_search(value: &JsValue, context: &mut Context) -> JsResult<SearchPolicy> {
+    if let Some(object) = value.as_object() {
+        let threshold = object.get("first", context)?;
+        if threshold.is_undefined() {
+            return Err(context.construct_type_error("search 对象必须是 {first: 阈值}"));
+        }
+        return Ok(SearchPolicy::First(threshold.to_number(context)? as f32));
+    }
+    let name = value.to_string(context)?;
+    match name.to_ascii_lowercase().as_str() {
+        "best" => Ok(SearchPolicy::Best),
+        "first" => Ok(SearchPolicy::First(SearchPolicy::DEFAULT_FIRST_THRESHOLD)),
+        _ => Err(context.construct_range_error(format!("未知的 search 方式: {}", name))),
+    }
+}
+
+/// 读取预处理步骤数组，元素为步骤名（使用默认参数）或 {步骤名: 参数} 对象：
+/// ["grayscale", {contrast: 1.5}, "equalize", {threshold: 128}, {blur: 1.0}, {edges: [50, 100]}]
+fn parse_preprocess(value: &JsValue, context: &mut Context) -> JsResult<Vec<Step>> {
+    if value.is_null() {
+        return Ok(Vec::new());
+    }
+    let Some(array) = value.as_object() else {
+        return Err(context.construct_type_error("preprocess 必须是数组"));
     };
+    let length = array.get("length", context)?.to_u32(context)?;
+    let mut steps = Vec::new();
+    for i in 0..length {
+        let item = array.get(i, context)?;
+        let step = if let Some(name) = item.as_string() {
+            match Step::parse(name) {
+                Some(step) => step,
+                None => return Err(context.construct_range_error(format!("未知的预处理步骤: {}", name))),
+            }
+        } else if let Some(object) = item.as_object() {
+            if let Some(factor) = object.get("contrast", context)?.as_number() {
+                Step::Contrast(factor as f32)
+            } else if let Some(level) = object.get("threshold", context)?.as_number() {
+                Step::Threshold(Some(level.clamp(0.0, 255.0) as u8))
+            } else if let Some(sigma) = object.get("blur", context)?.as_number() {
+                if !(sigma.is_finite() && sigma > 0.0) {
+                    return Err(context.construct_range_error(format!("blur 的 sigma 必须是大于 0 的有限数: {}", sigma)));
+                }
+                Step::Blur(sigma as f32)
+            } else if let Some(edges) = object.get("edges", context)?.as_object() {
+                let low = edges.get(0, context)?.to_number(context)?;
+                let high = edges.get(1, context)?.to_number(context)?;
+                if !(low.is_finite() && high.is_finite() && low <= high) {
+                    return Err(context.construct_range_error(format!("edges 的阈值必须是有限数且低阈值不大于高阈值: [{}, {}]", low, high)));
+                }
+                Step::Edges(low as f32, high as f32)
+            } else {
+                return Err(context.construct_range_error("无法识别的预处理步骤"));
+            }
+        } else {
+            return Err(context.construct_type_error("预处理步骤必须是字符串或对象"));
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+fn js_set_match_options(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let current = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(args.get(0).unwrap_or(&JsValue::undefined()), &current, context)?;
+    info!("全局匹配选项: {:?}", options);
+    *MATCH_OPTIONS.lock().unwrap() = options;
+    Ok(JsValue::undefined())
+}
+
+/// 毫秒数或 [min, max] 形式的随机范围
+fn ms_range(value: &JsValue, name: &str, context: &mut Context) -> JsResult<(u64, u64)> {
+    let (min, max) = match value.as_object().filter(|object| object.is_array()) {
+        Some(object) => (object.get(0, context)?.to_number(context)?, object.get(1, context)?.to_number(context)?),
+        None => {
+            let ms = value.to_number(context)?;
+            (ms, ms)
+        }
+    };
+    if !(min >= 0.0 && max >= min && max.is_finite()) {
+        return Err(context.construct_range_error(format!("{} 范围无效: [{}, {}]", name, min, max)));
+    }
+    Ok((min as u64, max as u64))
+}
+
+/// 解析鼠标选项对象，未出现的字段沿用 base
+fn parse_mouse_options(value: &JsValue, base: &MotionOptions, context: &mut Context) -> JsResult<MotionOptions> {
+    let mut options = base.clone();
+    let Some(object) = value.as_object() else {
+        return Ok(options);
+    };
+    let curve = object.get("curve", context)?;
+    if !curve.is_undefined() {
+        let name = curve.to_string(context)?;
+        options.curve = match Curve::parse(&name) {
+            Some(curve) => curve,
+            None => return Err(context.construct_range_error(format!("未知的鼠标轨迹: {}", name))),
+        };
+    }
+    let speed = object.get("speed", context)?;
+    if !speed.is_undefined() {
+        options.speed = speed.to_number(context)?;
+        if options.speed.is_nan() || options.speed <= 0.0 {
+            return Err(context.construct_range_error(format!("speed 必须大于 0: {}", options.speed)));
+        }
+    }
+    let overshoot = object.get("overshoot", context)?;
+    if !overshoot.is_undefined() {
+        let overshoot = overshoot.to_number(context)?;
+        if !overshoot.is_finite() {
+            return Err(context.construct_range_error(format!("overshoot 必须是有限数: {}", overshoot)));
+        }
+        options.overshoot = overshoot.max(0.0);
+    }
+    let jitter = object.get("jitter", context)?;
+    if !jitter.is_undefined() {
+        let jitter = jitter.to_number(context)?;
+        if !jitter.is_finite() {
+            return Err(context.construct_range_error(format!("jitter 必须是有限数: {}", jitter)));
+        }
+        options.jitter = jitter.clamp(0.0, 1.0);
+    }
+    let click_delay = object.get("clickDelay", context)?;
+    if !click_delay.is_undefined() {
+        options.click_delay = ms_range(&click_delay, "clickDelay", context)?;
+    }
+    let press_duration = object.get("pressDuration", context)?;
+    if !press_duration.is_undefined() {
+        options.press_duration = ms_range(&press_duration, "pressDuration", context)?;
+    }
+    Ok(options)
+}
+
+/// setMouseOptions({curve, speed, overshoot, jitter, clickDelay, pressDuration, seed})：设置全局鼠标选项
+fn js_set_mouse_options(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let value = args.first().cloned().unwrap_or_default();
+    let current = MOUSE_OPTIONS.lock().unwrap().clone();
+    let options = parse_mouse_options(&value, &current, context)?;
+    if let Some(object) = value.as_object() {
+        let seed = object.get("seed", context)?;
+        if seed.is_null() {
+            motion::reseed_from_entropy();
+        } else if !seed.is_undefined() {
+            let seed = seed.to_number(context)?;
+            if !seed.is_finite() {
+                return Err(context.construct_range_error(format!("seed 必须是有限数: {}", seed)));
+            }
+            motion::reseed(seed as u64);
+        }
+    }
+    info!("全局鼠标选项: {:?}", options);
+    *MOUSE_OPTIONS.lock().unwrap() = options;
+    Ok(JsValue::undefined())
+}
+
+/// preloadTemplates([file, ...], [options])：提前读取模板并按匹配选项准备好，返回成功的个数
+fn js_preload_templates(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let Some(list) = args.get(0).and_then(|arg| arg.as_object()) else {
+        return context.throw_type_error("preloadTemplates 需要模版文件数组");
+    };
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(args.get(1).unwrap_or(&JsValue::undefined()), &global_options, context)?;
+    let length = list.get("length", context)?.to_u32(context)?;
+    let mut files = Vec::new();
+    for i in 0..length {
+        files.push(list.get(i, context)?.to_string(context)?.to_string());
+    }
+    let start = Instant::now();
+    let loaded = files
+        .par_iter()
+        .filter(|file| templates::load(file).and_then(|image| prepare_template(file, &image, 1.0, 0.0, &options)).is_some())
+        .count();
+    info!("预加载模版 {}/{} 个, 耗时: {:?}", loaded, files.len(), start.elapsed());
+    Ok(JsValue::Integer(loaded as i32))
+}
+
+/// 读取数字参数，缺省或不是数字时返回 None
+fn number_arg(args: &[JsValue], index: usize) -> Option<f64> {
+    args.get(index).and_then(|arg| arg.as_number())
+}
+
+fn js_find_all_templates(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(window) = target_window() else {
+        return js_array(context, Vec::new());
+    };
+    find_all_templates_in(args, context, || capture_frame(&window))
+}
+
+/// findAllTemplates(file, [x, y, w, h], [options]) 的参数解析与结果转换，参数解析完成后才调用 frame 取得截图
+fn find_all_templates_in(args: &[JsValue], context: &mut Context, frame: impl FnOnce() -> Option<Arc<Frame>>) -> JsResult<JsValue> {
+    let Some(png_file) = args.get(0).and_then(|arg| arg.as_string()).map(|s| s.to_string()) else {
+        return context.throw_type_error("findAllTemplates 需要模版文件名");
+    };
+    let (region, options_arg) = region_and_options(args);
+
+    let mut threshold = 0.8;
+    let mut max_results = 0;
+    if let Some(object) = options_arg.as_object() {
+        let value = object.get("threshold", context)?;
+        if !value.is_undefined() {
+            threshold = value.to_number(context)? as f32;
+        }
+        let value = object.get("maxResults", context)?;
+        if !value.is_undefined() {
+            max_results = value.to_u32(context)? as usize;
+        }
+    }
+
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(&options_arg, &global_options, context)?;
+    let Some(frame) = frame() else {
+        return js_array(context, Vec::new());
+    };
+    let results = find_all_templates(&frame, &png_file, region, threshold, max_results, &options)
+        .iter()
+        .map(|found| match_object(context, found))
+        .collect();
+    js_array(context, results)
+}
+
+/// 读取 (x, y, w, h) 搜索区域参数与其后的选项对象；第二个参数就是对象时表示不限区域
+fn region_and_options(args: &[JsValue]) -> (Option<(u32, u32, u32, u32)>, JsValue) {
+    region_and_options_at(args, 1)
+}
+
+/// 同 region_and_options，区域参数从 index 开始
+fn region_and_options_at(args: &[JsValue], index: usize) -> (Option<(u32, u32, u32, u32)>, JsValue) {
+    match args.get(index) {
+        Some(arg) if arg.is_object() => (None, arg.clone()),
+        _ => {
+            let region = match (number_arg(args, index), number_arg(args, index + 1), number_arg(args, index + 2), number_arg(args, index + 3)) {
+                (Some(x), Some(y), Some(w), Some(h)) if x >= 0.0 && y >= 0.0 && w > 0.0 && h > 0.0 => {
+                    Some((x as u32, y as u32, w as u32, h as u32))
+                }
+                _ => None,
+            };
+            (region, args.get(index + 4).cloned().unwrap_or_default())
+        }
+    }
+}
+
+fn js_locate(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(png_file) = args.get(0).and_then(|arg| arg.as_string()).map(|s| s.to_string()) else {
+        return context.throw_type_error("locate 需要模版文件名");
+    };
+    let (region, options_arg) = region_and_options(args);
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(&options_arg, &global_options, context)?;
+    let Some(window) = target_window() else {
+        return Ok(JsValue::null());
+    };
+    match locate_template(&window, &png_file, region, &options) {
+        Some(found) => Ok(match_object(context, &found)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// 保存一帧截图，返回 JavaScript 快照对象
+fn frame_object(context: &mut Context, frame: Arc<Frame>) -> JsValue {
+    let id = NEXT_FRAME_ID.fetch_add(1, Ordering::SeqCst);
+    let (window_id, width, height, timestamp) = (frame.window_id, frame.image.width(), frame.image.height(), frame.timestamp);
+    {
+        let mut frames = FRAMES.lock().unwrap();
+        frames.push_back((id, frame));
+        while frames.len() > MAX_FRAMES {
+            frames.pop_front();
+        }
+    }
+    ObjectInitializer::new(context)
+        .property("frameId", id, Attribute::all())
+        .property("windowId", window_id, Attribute::all())
+        .property("width", width, Attribute::all())
+        .property("height", height, Attribute::all())
+        .property("timestamp", timestamp, Attribute::all())
+        .function(js_frame_find_template, "findTemplate", 1)
+        .function(js_frame_find_all_templates, "findAllTemplates", 1)
+        .function(js_frame_find_any, "findAny", 1)
+        .function(js_frame_get_pixel, "getPixel", 2)
+        .function(js_frame_save, "save", 1)
+        .build()
+        .into()
+}
+
+/// 取出快照对象 this 对应的截图，已被释放时抛出错误
+fn this_frame(this: &JsValue, context: &mut Context) -> JsResult<Arc<Frame>> {
+    let Some(object) = this.as_object() else {
+        return Err(context.construct_type_error("必须在快照对象上调用"));
+    };
+    let id = object.get("frameId", context)?.to_u32(context)?;
+    let frame = FRAMES.lock().unwrap().iter().find(|(frame_id, _)| *frame_id == id).map(|(_, frame)| frame.clone());
+    frame.ok_or_else(|| context.construct_error(format!("快照 {} 已释放，只保留最近 {} 个快照", id, MAX_FRAMES)))
+}
+
+/// snapshot()：截取目标窗口一次，返回快照对象，没有目标窗口时返回 null
+fn js_snapshot(_this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    match target_window().and_then(|window| capture_frame(&window)) {
+        Some(frame) => Ok(frame_object(context, frame)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// 窗口句柄的 snapshot()
+fn js_window_snapshot(this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    match this_window(this, context)?.and_then(|window| capture_frame(&window)) {
+        Some(frame) => Ok(frame_object(context, frame)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// 快照的 findTemplate(file, [x, y, w, h], [options])，返回匹配对象或 null
+fn js_frame_find_template(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let frame = this_frame(this, context)?;
+    let Some(png_file) = args.get(0).and_then(|arg| arg.as_string()).map(|s| s.to_string()) else {
+        return context.throw_type_error("findTemplate 需要模版文件名");
+    };
+    let (region, options_arg) = region_and_options(args);
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(&options_arg, &global_options, context)?;
+    match locate_in_frame(&frame, &png_file, region, &options) {
+        Some(found) => Ok(match_object(context, &found)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// 快照的 findAllTemplates(file, [x, y, w, h], [options])
+fn js_frame_find_all_templates(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let frame = this_frame(this, context)?;
+    find_all_templates_in(args, context, || Some(frame))
+}
+
+/// 读取 [x, y, w, h] 数组形式的区域
+fn region_array(value: &JsValue, context: &mut Context) -> JsResult<Option<(u32, u32, u32, u32)>> {
+    let Some(object) = value.as_object() else {
+        return Ok(None);
+    };
+    let mut region = [0u32; 4];
+    for (i, v) in region.iter_mut().enumerate() {
+        *v = object.get(i, context)?.to_u32(context)?;
+    }
+    let [x, y, w, h] = region;
+    Ok(if w > 0 && h > 0 { Some((x, y, w, h)) } else { None })
+}
+
+/// findAny 的一项：(模板文件, 搜索区域)
+type TemplateRegion = (String, Option<(u32, u32, u32, u32)>);
+
+/// 在同一帧中并行匹配多个 (模板文件, 搜索区域)，返回列表顺序中第一个得分达到阈值的序号与匹配
+fn find_any(frame: &Frame, templates: &[TemplateRegion], threshold: f32, options: &MatchOptions) -> Option<(usize, TemplateMatch)> {
+    let start = Instant::now();
+    let results: Vec<Option<TemplateMatch>> = templates
+        .par_iter()
+        .map(|(file, region)| locate_in_frame(frame, file, *region, options))
+        .collect();
+    info!("findAny 匹配 {} 个模版, 耗时: {:?}", templates.len(), start.elapsed());
+    results
+        .into_iter()
+        .enumerate()
+        .find_map(|(index, found)| found.filter(|found| found.score >= threshold).map(|found| (index, found)))
+}
+
+/// 快照的 findAny([file | {file, region: [x, y, w, h]}, ...], [x, y, w, h], [options])：
+/// 在同一帧上并行匹配所有模板，返回列表中第一个匹配率达到 threshold 的匹配对象
+/// （附带 template 文件名与 index 序号），都未达到时返回 null
+fn js_frame_find_any(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let frame = this_frame(this, context)?;
+    let Some(list) = args.get(0).and_then(|arg| arg.as_object()) else {
+        return context.throw_type_error("findAny 需要模版文件数组");
+    };
+    let (region, options_arg) = region_and_options(args);
+    let mut threshold = 0.8;
+    if let Some(object) = options_arg.as_object() {
+        let value = object.get("threshold", context)?;
+        if !value.is_undefined() {
+            threshold = value.to_number(context)? as f32;
+        }
+    }
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(&options_arg, &global_options, context)?;
+
+    let length = list.get("length", context)?.to_u32(context)?;
+    let mut templates = Vec::new();
+    for i in 0..length {
+        let item = list.get(i, context)?;
+        if let Some(file) = item.as_string() {
+            templates.push((file.to_string(), region));
+            continue;
+        }
+        let Some(object) = item.as_object() else {
+            return context.throw_type_error("findAny 的每一项必须是文件名或 {file, region}");
+        };
+        let Some(file) = object.get("file", context)?.as_string().map(|s| s.to_string()) else {
+            return context.throw_type_error("findAny 的每一项必须是文件名或 {file, region}");
+        };
+        let item_region = region_array(&object.get("region", context)?, context)?.or(region);
+        templates.push((file, item_region));
+    }
+
+    let Some((index, found)) = find_any(&frame, &templates, threshold, &options) else {
+        return Ok(JsValue::null());
+    };
+    let object = match_object(context, &found);
+    if let Some(o) = object.as_object() {
+        o.set("template", templates[index].0.as_str(), true, context)?;
+        o.set("index", index as u32, true, context)?;
+    }
+    Ok(object)
+}
+
+/// 快照的 getPixel(x, y)，坐标超出截图时返回 null
+fn js_frame_get_pixel(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let frame = this_frame(this, context)?;
+    let (Some(x), Some(y)) = (number_arg(args, 0), number_arg(args, 1)) else {
+        return context.throw_type_error("getPixel 需要坐标 x, y");
+    };
+    if x < 0.0 || y < 0.0 || x as u32 >= frame.image.width() || y as u32 >= frame.image.height() {
+        return Ok(JsValue::null());
+    }
+    Ok(pixel_object(context, frame.image.get_pixel(x as u32, y as u32)))
+}
+
+/// 快照的 save(file)，把这一帧保存到文件
+fn js_frame_save(this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let frame = this_frame(this, context)?;
+    let Some(file) = args.get(0).and_then(|arg| arg.as_string()).map(|s| s.to_string()) else {
+        return context.throw_type_error("save 需要保存的文件名");
+    };
+    match frame.image.save(&file) {
+        Ok(()) => Ok(JsValue::Boolean(true)),
+        Err(e) => {
+            error!("保存截图失败，{:?}: {:?}", file, e);
+            Ok(JsValue::Boolean(false))
+        }
+    }
+}
+
+/// 用特征点匹配在窗口中查找模板，返回的坐标、尺寸为变换后模板的外接矩形
+fn locate_feature(window: &WindowInfo, template_file: &str, region: Option<(u32, u32, u32, u32)>, options: &MatchOptions, feature_options: &FeatureOptions) -> Option<TemplateMatch> {
+    let template_image = templates::load(template_file)?;
+    let (dynamic_img, offset_x, offset_y, timestamp) = capture_region(window, region)?;
+    let mask = template_mask(&template_image, options)?;
+    let dynamic_img = preprocess::apply(&dynamic_img, &options.preprocess);
+    let template_image = preprocess::apply(&template_image, &options.preprocess);
+    let start = Instant::now();
+    let found = find_feature(&dynamic_img, &template_image, mask.as_ref(), feature_options);
+    info!("特征匹配结果: {:?}, 耗时: {:?}, {}", found, start.elapsed(), template_file);
+    let found = found?;
+    // 先按完整的外接矩形求模板中心，再把超出截图的部分裁掉
+    let center_x = (found.x + found.width as i32 / 2).clamp(0, dynamic_img.width() as i32 - 1) as u32;
+    let center_y = (found.y + found.height as i32 / 2).clamp(0, dynamic_img.height() as i32 - 1) as u32;
+    let left = found.x.max(0) as u32;
+    let top = found.y.max(0) as u32;
+    let right = ((found.x + found.width as i32).max(0) as u32).min(dynamic_img.width());
+    let bottom = ((found.y + found.height as i32).max(0) as u32).min(dynamic_img.height());
+    Some(TemplateMatch {
+        window_id: window.id,
+        x: offset_x + left,
+        y: offset_y + top,
+        width: right.saturating_sub(left),
+        height: bottom.saturating_sub(top),
+        center: (offset_x + center_x, offset_y + center_y),
+        score: found.score,
+        scale: found.scale,
+        angle: found.angle,
+        timestamp,
+    })
+}
+
+/// 读取特征匹配选项 {fastThreshold, maxFeatures, minInliers}
+fn parse_feature_options(value: &JsValue, context: &mut Context) -> JsResult<FeatureOptions> {
+    let mut options = FeatureOptions::default();
+    let Some(object) = value.as_object() else {
+        return Ok(options);
+    };
+    let fast_threshold = object.get("fastThreshold", context)?;
+    if !fast_threshold.is_undefined() {
+        options.fast_threshold = fast_threshold.to_u32(context)?.min(255) as u8;
+    }
+    let max_features = object.get("maxFeatures", context)?;
+    if !max_features.is_undefined() {
+        options.max_features = max_features.to_u32(context)? as usize;
+    }
+    let min_inliers = object.get("minInliers", context)?;
+    if !min_inliers.is_undefined() {
+        options.min_inliers = min_inliers.to_u32(context)? as usize;
+    }
+    Ok(options)
+}
+
+/// findFeature(file, [x, y, w, h], [options])：特征点匹配，返回与 locate 相同的匹配对象或 null
+fn js_find_feature(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let Some(png_file) = args.get(0).and_then(|arg| arg.as_string()).map(|s| s.to_string()) else {
+        return context.throw_type_error("findFeature 需要模版文件名");
+    };
+    let (region, options_arg) = region_and_options(args);
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(&options_arg, &global_options, context)?;
+    let feature_options = parse_feature_options(&options_arg, context)?;
+    let Some(window) = target_window() else {
+        return Ok(JsValue::null());
+    };
+    match locate_feature(&window, &png_file, region, &options, &feature_options) {
+        Some(found) => Ok(match_object(context, &found)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// 读取颜色参数："#RRGGBB" / "0xRRGGBB" 字符串、0xRRGGBB 数字或 [r, g, b] 数组
+fn parse_color(value: &JsValue, context: &mut Context) -> JsResult<Rgb<u8>> {
+    if let Some(text) = value.as_string() {
+        let text = text.to_string();
+        return color::parse_hex(&text).ok_or_else(|| context.construct_range_error(format!("无法识别的颜色: {}", text)));
+    }
+    if let Some(number) = value.as_number() {
+        if !(0.0..=0xFFFFFF as f64).contains(&number) || number.fract() != 0.0 {
+            return Err(context.construct_range_error(format!("颜色数值必须是 0 到 0xFFFFFF 之间的整数: {}", number)));
+        }
+        return Ok(color::from_u32(number as u32));
+    }
+    if let Some(object) = value.as_object() {
+        let mut rgb = [0u8; 3];
+        for (i, channel) in rgb.iter_mut().enumerate() {
+            *channel = object.get(i, context)?.to_u32(context)?.min(255) as u8;
+        }
+        return Ok(Rgb(rgb));
+    }
+    Err(context.construct_type_error("颜色必须是 \"#RRGGBB\" 字符串、数字或 [r, g, b] 数组"))
+}
+
+/// 读取颜色容差参数，缺省为 0
+fn tolerance_arg(args: &[JsValue], index: usize) -> u8 {
+    number_arg(args, index).unwrap_or(0.0).clamp(0.0, 255.0) as u8
+}
+
+/// 截取颜色查询的区域，返回 RGBA 截图与区域左上角
+fn capture_rgba(region: Option<(u32, u32, u32, u32)>) -> Option<(RgbaImage, u32, u32)> {
+    let window = target_window()?;
+    let (image, offset_x, offset_y, _) = capture_region(&window, region)?;
+    Some((image.into_rgba8(), offset_x, offset_y))
+}
+
+/// 像素颜色转换为 JavaScript 对象 {r, g, b, a, hex}
+fn pixel_object(context: &mut Context, pixel: &Rgba<u8>) -> JsValue {
+    ObjectInitializer::new(context)
+        .property("r", pixel[0] as u32, Attribute::all())
+        .property("g", pixel[1] as u32, Attribute::all())
+        .property("b", pixel[2] as u32, Attribute::all())
+        .property("a", pixel[3] as u32, Attribute::all())
+        .property("hex", color::to_hex(pixel), Attribute::all())
+        .build()
+        .into()
+}
+
+/// 坐标转换为 JavaScript 对象 {x, y}
+fn point_object(context: &mut Context, x: u32, y: u32) -> JsValue {
+    ObjectInitializer::new(context)
+        .property("x", x, Attribute::all())
+        .property("y", y, Attribute::all())
+        .build()
+        .into()
+}
+
+/// getPixel(x, y)：返回该点颜色，坐标超出窗口时返回 null。
+/// 读取目标窗口最近一次的截图（查找、找色、快照都会更新），连续读取多个点只截图一次
+fn js_get_pixel(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let (Some(x), Some(y)) = (number_arg(args, 0), number_arg(args, 1)) else {
+        return context.throw_type_error("getPixel 需要坐标 x, y");
+    };
+    let Some(frame) = target_window().and_then(|window| recent_frame(&window)) else {
+        return Ok(JsValue::null());
+    };
+    let image = &frame.image;
+    if x < 0.0 || y < 0.0 || x as u32 >= image.width() || y as u32 >= image.height() {
+        error!("坐标超出窗口区域，({}, {}) 窗口 {}x{}", x, y, image.width(), image.height());
+        return Ok(JsValue::null());
+    }
+    Ok(pixel_object(context, image.get_pixel(x as u32, y as u32)))
+}
+
+/// findColor(color, tolerance, [x, y, w, h])：返回第一个颜色相符的像素 {x, y} 或 null
+fn js_find_color(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let color = parse_color(&args.get(0).cloned().unwrap_or_default(), context)?;
+    let tolerance = tolerance_arg(args, 1);
+    let (region, _) = region_and_options_at(args, 2);
+    let Some((image, offset_x, offset_y)) = capture_rgba(region) else {
+        return Ok(JsValue::null());
+    };
+    let start = Instant::now();
+    let found = color::find_color(&image, color, tolerance, 1).first().copied();
+    info!("找色结果: {:?}, 耗时: {:?}", found, start.elapsed());
+    match found {
+        Some((x, y)) => Ok(point_object(context, offset_x + x, offset_y + y)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// findAllColors(color, tolerance, [x, y, w, h], [options])：返回所有颜色相符的像素
+fn js_find_all_colors(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let color = parse_color(&args.get(0).cloned().unwrap_or_default(), context)?;
+    let tolerance = tolerance_arg(args, 1);
+    let (region, options_arg) = region_and_options_at(args, 2);
+    let mut max_results = 0;
+    if let Some(object) = options_arg.as_object() {
+        let value = object.get("maxResults", context)?;
+        if !value.is_undefined() {
+            max_results = value.to_u32(context)? as usize;
+        }
+    }
+    let Some((image, offset_x, offset_y)) = capture_rgba(region) else {
+        return js_array(context, Vec::new());
+    };
+    let points = color::find_color(&image, color, tolerance, max_results)
+        .into_iter()
+        .map(|(x, y)| point_object(context, offset_x + x, offset_y + y))
+        .collect();
+    js_array(context, points)
+}
+
+/// findMultiColor(baseColor, [[dx, dy, color], ...], tolerance, [x, y, w, h])：
+/// 返回第一个满足所有偏移点颜色的基准点 {x, y} 或 null
+fn js_find_multi_color(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let base = parse_color(&args.get(0).cloned().unwrap_or_default(), context)?;
+    let Some(list) = args.get(1).and_then(|arg| arg.as_object()) else {
+        return context.throw_type_error("findMultiColor 需要偏移点数组 [[dx, dy, color], ...]");
+    };
+    let length = list.get("length", context)?.to_u32(context)?;
+    let mut points = Vec::new();
+    for i in 0..length {
+        let point = list.get(i, context)?;
+        let Some(point) = point.as_object() else {
+            return context.throw_type_error("偏移点必须是 [dx, dy, color] 数组");
+        };
+        let dx = point.get(0, context)?.to_i32(context)?;
+        let dy = point.get(1, context)?.to_i32(context)?;
+        let color = parse_color(&point.get(2, context)?, context)?;
+        points.push((dx, dy, color));
+    }
+    let tolerance = tolerance_arg(args, 2);
+    let (region, _) = region_and_options_at(args, 3);
+    let Some((image, offset_x, offset_y)) = capture_rgba(region) else {
+        return Ok(JsValue::null());
+    };
+    let start = Instant::now();
+    let found = color::find_multi_color(&image, base, &points, tolerance);
+    info!("多点找色结果: {:?}, 耗时: {:?}", found, start.elapsed());
+    match found {
+        Some((x, y)) => Ok(point_object(context, offset_x + x, offset_y + y)),
+        None => Ok(JsValue::null()),
+    }
+}
+
+/// waitForTemplate / waitUntilGone 的等待参数
+struct WaitOptions {
+    /// 超时时间，None 表示一直等待
+    timeout: Option<time::Duration>,
+    interval: time::Duration,
+    threshold: f32,
+}
+
+/// 读取 {timeout, interval, threshold}，timeout 为 0 或 Infinity 时一直等待
+fn parse_wait_options(value: &JsValue, context: &mut Context) -> JsResult<WaitOptions> {
+    let mut options = WaitOptions {
+        timeout: Some(time::Duration::from_millis(10000)),
+        interval: time::Duration::from_millis(500),
+        threshold: 0.8,
+    };
+    let Some(object) = value.as_object() else {
+        return Ok(options);
+    };
+    let timeout = object.get("timeout", context)?;
+    if !timeout.is_undefined() {
+        let ms = timeout.to_number(context)?;
+        options.timeout = if ms > 0.0 && ms.is_finite() {
+            Some(time::Duration::from_millis(ms as u64))
+        } else {
+            None
+        };
+    }
+    let interval = object.get("interval", context)?;
+    if !interval.is_undefined() {
+        options.interval = time::Duration::from_millis(interval.to_number(context)?.max(0.0) as u64);
+    }
+    let threshold = object.get("threshold", context)?;
+    if !threshold.is_undefined() {
+        options.threshold = threshold.to_number(context)? as f32;
+    }
+    Ok(options)
+}
+
+/// 按间隔轮询模板，直到出现（gone 为 false）或消失（gone 为 true）。
+/// 暂停期间不计入超时，超时返回 Ok(Err(()))；每次轮询都经过安全点，中止或回调抛出的异常直接返回。
+/// 没有目标窗口、截图失败、模板读取失败或搜索区域无效时抛出错误，只有查找完成且没有达到阈值才算消失
+fn wait_template(template_file: &str, region: Option<(u32, u32, u32, u32)>, options: &MatchOptions, wait: &WaitOptions, gone: bool, context: &mut Context) -> JsResult<Result<Option<TemplateMatch>, ()>> {
+    let mut paused = time::Duration::ZERO;
+    let start = Instant::now();
+    loop {
+        paused += checkpoint(context)?;
+        let Some(window) = target_window() else {
+            return Err(context.construct_error("没有目标窗口，请先调用 findWindow"));
+        };
+        let Some(frame) = capture_frame(&window) else {
+            return Err(context.construct_error(format!("截取窗口失败: {}", window.title)));
+        };
+        let found = match try_locate_in_frame(&frame, template_file, region, options) {
+            Ok(found) => found.filter(|found| found.score >= wait.threshold),
+            Err(e) => return Err(context.construct_error(e)),
+        };
+        match (gone, found) {
+            (false, Some(found)) => {
+                remember_match(Some(found.clone()));
+                return Ok(Ok(Some(found)));
+            }
+            (true, None) => return Ok(Ok(None)),
+            _ => {}
+        }
+        if let Some(timeout) = wait.timeout {
+            if start.elapsed().saturating_sub(paused) >= timeout {
+                return Ok(Err(()));
+            }
+        }
+        paused += safe_sleep(wait.interval, context)?;
+    }
+}
+
+/// waitForTemplate 与 waitUntilGone 共用的参数解析
+fn js_wait(args: &[JsValue], context: &mut Context, name: &str, gone: bool) -> JsResult<JsValue> {
+    let Some(png_file) = args.get(0).and_then(|arg| arg.as_string()).map(|s| s.to_string()) else {
+        return context.throw_type_error(format!("{} 需要模版文件名", name));
+    };
+    let (region, options_arg) = region_and_options(args);
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(&options_arg, &global_options, context)?;
+    let wait = parse_wait_options(&options_arg, context)?;
+    match wait_template(&png_file, region, &options, &wait, gone, context)? {
+        Ok(Some(found)) => Ok(match_object(context, &found)),
+        Ok(None) => Ok(JsValue::Boolean(true)),
+        Err(()) => {
+            let timeout = wait.timeout.unwrap_or_default().as_millis();
+            context.throw_error(format!("{} 超时 ({} ms): {}", name, timeout, png_file))
+        }
+    }
+}
+
+/// waitForTemplate(file, [x, y, w, h], [options])：等待模板出现，返回匹配对象，超时抛出错误
+fn js_wait_for_template(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    js_wait(args, context, "waitForTemplate", false)
+}
+
+/// waitUntilGone(file, [x, y, w, h], [options])：等待模板消失，返回 true，超时抛出错误
+fn js_wait_until_gone(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    js_wait(args, context, "waitUntilGone", true)
+}
+
+fn js_find_template(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(_context)?;
+    // 获取第一个参数（假设它是数字）
+    let png_file = if let Some(arg) = _args.get(0) {
+        arg.as_string().map(|n| n)
+    } else {
+        None
+    };
+
+    // 选项对象可以紧跟文件名 findTemplate(file, options)，也可以放在区域之后
+    let options_arg = match _args.get(1) {
+        Some(arg) if arg.is_object() => arg.clone(),
+        _ => _args.get(5).cloned().unwrap_or_default(),
+    };
+    let global_options = MATCH_OPTIONS.lock().unwrap().clone();
+    let options = parse_match_options(&options_arg, &global_options, _context)?;
+
+    let offset_x = if let Some(arg) = _args.get(1) {
+        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
+    } else {
+        0
+    };
+
+    let offset_y = if let Some(arg) = _args.get(2) {
+        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
+    } else {
+        0
+    };
+
+    let width = if let Some(arg) = _args.get(3) {
+        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
+    } else {
+        0
+    };
+
+    let height = if let Some(arg) = _args.get(4) {
+        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
+    } else {
+        0
+    };
+
+    // 如果没有提供有效的参数，则返回 undefined
+    let result = match png_file  {
+        Some(js_str) => {
+            //let mut ncc:f32 = 0.0;
+            if offset_x>=0 && offset_y>=0 && width>0 && height>0 {
+                let ncc = find_template_cut(js_str.to_string(), offset_x as u32, offset_y as u32, width as u32, height as u32, &options ).into();
+                JsValue::Rational(ncc)
+            }else {
+                let ncc = find_template(js_str.to_string(), &options).into();
+                JsValue::Rational(ncc)
+            }
+        },
+        None => JsValue::Rational(0.0),
+    };
+    Ok(result)
+}
+
+
+fn js_click(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(_context)?;
+    let (x, y) = point_arg(_args, 0);
+    if let Some(window) = target_window() {
+        return Ok(JsValue::Boolean(click_at(&window, x, y)));
+    }
+    Ok(JsValue::Boolean(false))
+}
+fn click_at(window: &WindowInfo, x: i32, y: i32) -> bool {
+    button_at(window, x, y, Button::Left, 1)
+}
+
+/// 双击时两次点击之间的间隔
+const DOUBLE_CLICK_INTERVAL_MS: u64 = 80;
+/// 拖动未指定 duration 时的默认时长
+const DEFAULT_DRAG_DURATION_MS: f64 = 300.0;
+/// drag 的最长时长，拖动期间按键一直按着
+const MAX_DRAG_DURATION_MS: f64 = 60_000.0;
+
+/// 窗口截图坐标 (x, y) 转换为屏幕逻辑坐标，负坐标表示窗口左侧或上方
+fn screen_point(window: &WindowInfo, x: i32, y: i32) -> (i32, i32) {
+    let screen_scale_factor = platform::current().scale_factor();
+    let click_x = x as f32 / screen_scale_factor;
+    let click_y = y as f32 / screen_scale_factor;
+    (window.x + click_x as i32, window.y + click_y as i32)
+}
+
+/// 坐标参数 (x, y)，缺省或为 0, 0 时使用上次查找到的图片中心（按 jitter 随机偏移），与 click 一致
+fn point_arg(args: &[JsValue], index: usize) -> (i32, i32) {
+    let x = number_arg(args, index).unwrap_or(0.0) as i32;
+    let y = number_arg(args, index + 1).unwrap_or(0.0) as i32;
+    if x == 0 && y == 0 {
+        let rect = unsafe { (FIND_TEMPLATE_X, FIND_TEMPLATE_Y, LAST_TEMPLATE_WIDTH, LAST_TEMPLATE_HEIGHT) };
+        let (center_x, center_y) = motion::click_point(rect, MOUSE_OPTIONS.lock().unwrap().jitter);
+        info!("图片中心位置: {:?}, {:?}", center_x, center_y);
+        return (center_x as i32, center_y as i32);
+    }
+    (x, y)
+}
+
+/// 鼠标按键参数："left"、"right" 或 "middle"，缺省为 left
+fn button_arg(arg: Option<&JsValue>, context: &mut Context) -> JsResult<Button> {
+    let Some(arg) = arg.filter(|arg| !arg.is_undefined()) else {
+        return Ok(Button::Left);
+    };
+    let name = arg.to_string(context)?;
+    match name.to_ascii_lowercase().as_str() {
+        "left" => Ok(Button::Left),
+        "right" => Ok(Button::Right),
+        "middle" => Ok(Button::Middle),
+        _ => Err(context.construct_range_error(format!("鼠标按键只能是 left、right 或 middle: {}", name))),
+    }
+}
+
+/// 沿鼠标选项设定的轨迹移动到屏幕坐标，duration 为 None 时按速度计算时长
+fn glide_to(x: i32, y: i32, options: &MotionOptions, duration: Option<f64>) -> bool {
+    let platform = platform::current();
+    let path = match platform.mouse_location() {
+        Some(from) => motion::path(from, (x, y), options, duration),
+        None => vec![(x, y)],
+    };
+    let mut ok = true;
+    for (i, (px, py)) in path.into_iter().enumerate() {
+        if i > 0 {
+            thread::sleep(time::Duration::from_millis(motion::STEP_MS));
+        }
+        ok = platform.move_mouse(px, py);
+    }
+    ok
+}
+
+/// 把鼠标移动到窗口截图坐标 (x, y)
+fn move_to(window: &WindowInfo, x: i32, y: i32) -> bool {
+    let (screen_x, screen_y) = screen_point(window, x, y);
+    info!("鼠标位置: {:?}, {:?}", screen_x, screen_y);
+    let options = MOUSE_OPTIONS.lock().unwrap().clone();
+    glide_to(screen_x, screen_y, &options, None)
+}
+
+/// 按下并松开一次，按下时长按鼠标选项随机
+fn press_button(button: Button) -> bool {
+    let platform = platform::current();
+    let hold = motion::random_ms(MOUSE_OPTIONS.lock().unwrap().press_duration);
+    if hold == 0 {
+        return platform.mouse_button(button, Click);
+    }
+    let pressed = control::send_button(button, Press);
+    thread::sleep(time::Duration::from_millis(hold));
+    control::send_button(button, Release) && pressed
+}
+
+/// 移动到 (x, y)，等待 clickDelay 后用指定按键连续点击 count 次
+fn button_at(window: &WindowInfo, x: i32, y: i32, button: Button, count: u32) -> bool {
+    move_to(window, x, y);
+    let delay = motion::random_ms(MOUSE_OPTIONS.lock().unwrap().click_delay);
+    thread::sleep(time::Duration::from_millis(delay));
+    (0..count).all(|i| {
+        if i > 0 {
+            thread::sleep(time::Duration::from_millis(DOUBLE_CLICK_INTERVAL_MS));
+        }
+        press_button(button)
+    })
+}
+
+/// 按住 button 从 from 拖动到 to，用 duration 毫秒沿鼠标选项的轨迹移动（不越过终点）
+fn drag_between(window: &WindowInfo, from: (i32, i32), to: (i32, i32), button: Button, duration: f64) -> bool {
+    let (from_x, from_y) = screen_point(window, from.0, from.1);
+    let (to_x, to_y) = screen_point(window, to.0, to.1);
+    info!("拖动: ({:?}, {:?}) -> ({:?}, {:?})", from_x, from_y, to_x, to_y);
+    let options = MOUSE_OPTIONS.lock().unwrap().clone();
+    glide_to(from_x, from_y, &options, None);
+    thread::sleep(time::Duration::from_millis(motion::STEP_MS));
+    if !control::send_button(button, Press) {
+        return false;
+    }
+    let drag_options = MotionOptions { overshoot: 0.0, ..options };
+    glide_to(to_x, to_y, &drag_options, Some(duration));
+    thread::sleep(time::Duration::from_millis(motion::STEP_MS));
+    control::send_button(button, Release)
+}
+
+/// rightClick(x, y)：在窗口截图坐标处右键点击，坐标规则与 click 相同
+fn js_right_click(_this: &JsValue, args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(_context)?;
+    let (x, y) = point_arg(args, 0);
+    let Some(window) = target_window() else {
+        return Ok(JsValue::Boolean(false));
+    };
+    Ok(JsValue::Boolean(button_at(&window, x, y, Button::Right, 1)))
+}
+
+/// middleClick(x, y)：在窗口截图坐标处中键点击
+fn js_middle_click(_this: &JsValue, args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(_context)?;
+    let (x, y) = point_arg(args, 0);
+    let Some(window) = target_window() else {
+        return Ok(JsValue::Boolean(false));
+    };
+    Ok(JsValue::Boolean(button_at(&window, x, y, Button::Middle, 1)))
+}
+
+/// doubleClick(x, y, [button])：在窗口截图坐标处双击
+fn js_double_click(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let (x, y) = point_arg(args, 0);
+    let button = button_arg(args.get(2), context)?;
+    let Some(window) = target_window() else {
+        return Ok(JsValue::Boolean(false));
+    };
+    Ok(JsValue::Boolean(button_at(&window, x, y, button, 2)))
+}
+
+/// mouseDown/mouseUp 共用：([x, y], [button])，带坐标时先移动到该位置
+fn mouse_button_at(args: &[JsValue], direction: Direction, context: &mut Context) -> JsResult<JsValue> {
+    let has_point = number_arg(args, 0).is_some();
+    let button = button_arg(args.get(if has_point { 2 } else { 0 }), context)?;
+    if has_point {
+        let Some(window) = target_window() else {
+            return Ok(JsValue::Boolean(false));
+        };
+        let (x, y) = point_arg(args, 0);
+        move_to(&window, x, y);
+    }
+    Ok(JsValue::Boolean(control::send_button(button, direction)))
+}
+
+/// mouseDown([x, y], [button])：按下鼠标按键
+fn js_mouse_down(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    mouse_button_at(args, Press, context)
+}
+
+/// mouseUp([x, y], [button])：松开鼠标按键，中止后在 finally 块中也能调用
+fn js_mouse_up(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    if !control::is_aborted() {
+        checkpoint(context)?;
+    }
+    mouse_button_at(args, Release, context)
+}
+
+/// moveTo(x, y)：只移动鼠标，不点击
+fn js_move_to(_this: &JsValue, args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(_context)?;
+    let (x, y) = point_arg(args, 0);
+    let Some(window) = target_window() else {
+        return Ok(JsValue::Boolean(false));
+    };
+    Ok(JsValue::Boolean(move_to(&window, x, y)))
+}
+
+/// drag(fromX, fromY, toX, toY, [{duration, button}])：按住按键从起点拖动到终点
+fn js_drag(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let coordinates: Option<Vec<f64>> = (0..4).map(|i| number_arg(args, i)).collect();
+    let Some(coordinates) = coordinates else {
+        return context.throw_type_error("drag 需要 fromX, fromY, toX, toY");
+    };
+    let mut duration = DEFAULT_DRAG_DURATION_MS;
+    let mut button = Button::Left;
+    if let Some(object) = args.get(4).and_then(|arg| arg.as_object()) {
+        let value = object.get("duration", context)?;
+        if !value.is_undefined() {
+            duration = value.to_number(context)?;
+            if !(0.0..=MAX_DRAG_DURATION_MS).contains(&duration) {
+                return context.throw_range_error(format!("drag 的 duration 必须在 0 到 {} 毫秒之间: {}", MAX_DRAG_DURATION_MS, duration));
+            }
+        }
+        let value = object.get("button", context)?;
+        button = button_arg(Some(&value), context)?;
+    }
+    let Some(window) = target_window() else {
+        return Ok(JsValue::Boolean(false));
+    };
+    let from = (coordinates[0] as i32, coordinates[1] as i32);
+    let to = (coordinates[2] as i32, coordinates[3] as i32);
+    Ok(JsValue::Boolean(drag_between(&window, from, to, button, duration)))
+}
+
+/// scroll(dx, dy)：在鼠标当前位置滚动滚轮，正数向右、向下
+fn js_scroll(_this: &JsValue, args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(_context)?;
+    let dx = number_arg(args, 0).unwrap_or(0.0) as i32;
+    let dy = number_arg(args, 1).unwrap_or(0.0) as i32;
+    let platform = platform::current();
+    let mut ok = true;
+    if dy != 0 {
+        ok &= platform.scroll(dy, Vertical);
+    }
+    if dx != 0 {
+        ok &= platform.scroll(dx, Horizontal);
+    }
+    Ok(JsValue::Boolean(ok))
+}
+fn js_sleep(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+    let ms = if let Some(arg) = _args.get(0) {
+        arg.as_number().map(|n| n).unwrap_or(0.0)
+    } else {
+        0.0
+    };
+    safe_sleep(time::Duration::from_millis(ms as u64), _context)?;
+    Ok(JsValue::undefined())
+}
+
+/// 依次调用 pending 取出的各项对应的回调（callback 返回 None 的项跳过），已经在回调中时不嵌套执行（也不取出）。
+/// 回调抛出的异常传给调用处，之后还没处理的项交给 requeue
+fn run_callbacks<T>(
+    context: &mut Context,
+    pending: impl FnOnce() -> Vec<T>,
+    callback: impl Fn(&T) -> Option<JsObject>,
+    requeue: impl FnOnce(Vec<T>),
+) -> JsResult<()> {
+    if RUNNING_CALLBACKS.with(|running| running.replace(true)) {
+        return Ok(());
+    }
+    let mut result = Ok(());
+    let mut items = pending().into_iter();
+    for item in items.by_ref() {
+        let Some(function) = callback(&item) else {
+            continue;
+        };
+        result = function.call(&JsValue::undefined(), &[], context).map(|_| ());
+        if result.is_err() {
+            break;
+        }
+    }
+    RUNNING_CALLBACKS.with(|running| running.set(false));
+    let rest: Vec<T> = items.collect();
+    if !rest.is_empty() {
+        requeue(rest);
+    }
+    result
+}
 
-    let width = if let Some(arg) = _args.get(3) {
-        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
-    } else {
-        0
-    };
+/// 已中止时，第一次抛出错误让 finally 块执行；再次调用说明脚本捕获了错误还在继续运行，
+/// 松开脚本按住的键和鼠标按键后退出进程
+fn abort_check(context: &mut Context) -> JsResult<()> {
+    if !control::is_aborted() {
+        return Ok(());
+    }
+    if control::take_abort() {
+        return context.throw_error("脚本已中止").map(|_| ());
+    }
+    control::release_held();
+    info!("脚本已中止，退出");
+    process::exit(0);
+}
 
-    let height = if let Some(arg) = _args.get(4) {
-        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
-    } else {
-        0
-    };
+/// 安全点：已中止时抛出错误（见 abort_check）；暂停时执行 onPause 回调并阻塞到继续，再执行 onResume 回调；
+/// 最后执行已触发的热键回调。返回暂停的时长
+fn checkpoint(context: &mut Context) -> JsResult<time::Duration> {
+    let mut paused = time::Duration::ZERO;
+    loop {
+        abort_check(context)?;
+        // onPause/onResume 回调只属于那一次状态变化，其中一个出错后剩下的不再补调
+        run_callbacks(
+            context,
+            || {
+                control::take_events()
+                    .into_iter()
+                    .flat_map(|event| match event {
+                        control::Event::Paused => PAUSE_HOOKS.with(|hooks| hooks.borrow().clone()),
+                        control::Event::Resumed => RESUME_HOOKS.with(|hooks| hooks.borrow().clone()),
+                    })
+                    .collect()
+            },
+            |hook: &JsObject| Some(hook.clone()),
+            drop,
+        )?;
+        if !control::is_paused() {
+            break;
+        }
+        paused += control::wait_while_paused();
+    }
+    // 回调执行时才查找，前面的回调中 removeHotkey 的热键不再执行；出错时剩下的 id 放回队列
+    run_callbacks(
+        context,
+        hotkeys::take_fired,
+        |id| HOTKEY_CALLBACKS.with(|callbacks| callbacks.borrow().get(id).cloned()),
+        hotkeys::requeue,
+    )?;
+    Ok(paused)
+}
 
-    // 如果没有提供有效的参数，则返回 undefined
-    let result = match png_file  {
-        Some(js_str) => {
-            //let mut ncc:f32 = 0.0;
-            if offset_x>=0 && offset_y>=0 && width>0 && height>0 {
-                let ncc = find_template_cut(js_str.to_string(), offset_x as u32, offset_y as u32, width as u32, height as u32 ).into();
-                JsValue::Rational(ncc)
-            }else {
-                let ncc = find_template(js_str.to_string()).into();
-                JsValue::Rational(ncc)
+/// 等待一段时间，期间按热键轮询间隔经过安全点；暂停的时长不计入等待，返回暂停的时长
+fn safe_sleep(duration: time::Duration, context: &mut Context) -> JsResult<time::Duration> {
+    let mut paused = checkpoint(context)?;
+    let end = Instant::now() + duration;
+    loop {
+        let left = (end + paused).saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Ok(paused);
+        }
+        thread::sleep(left.min(hotkeys::POLL_INTERVAL));
+        paused += checkpoint(context)?;
+    }
+}
+
+/// 热键参数：按键名称或名称数组，名称与 isKeyDown 相同
+fn hotkey_arg(arg: Option<&JsValue>, context: &mut Context) -> JsResult<Vec<Keycode>> {
+    let names = match arg.and_then(|arg| arg.as_object()).filter(|object| object.is_array()) {
+        Some(list) => {
+            let length = list.get("length", context)?.to_u32(context)?;
+            let mut names = Vec::new();
+            for i in 0..length {
+                names.push(list.get(i, context)?.to_string(context)?.to_string());
             }
+            names
+        }
+        None => match arg.and_then(|arg| arg.as_string()) {
+            Some(name) => vec![name.to_string()],
+            None => return Err(context.construct_type_error("onHotkey 需要按键名称或名称数组")),
         },
-        None => JsValue::Rational(0.0),
     };
-    Ok(result)
+    if names.is_empty() {
+        return Err(context.construct_type_error("onHotkey 至少需要一个按键"));
+    }
+    names
+        .iter()
+        .map(|name| match name.trim().parse::<Keycode>() {
+            Ok(key) => Ok(key),
+            Err(_) => Err(context.construct_range_error(format!("未知的按键: {}", name))),
+        })
+        .collect()
 }
 
-
-fn js_click(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
-    let offset_x = if let Some(arg) = _args.get(0) {
-        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
-    } else {
-        0
+/// onHotkey(keys, fn)：组合键按下时在下一个安全点调用 fn，返回热键 id
+fn js_on_hotkey(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let keys = hotkey_arg(args.first(), context)?;
+    let Some(callback) = args.get(1).and_then(|arg| arg.as_object()).filter(|object| object.is_callable()) else {
+        return context.throw_type_error("onHotkey 需要回调函数");
     };
+    let id = hotkeys::register(keys);
+    HOTKEY_CALLBACKS.with(|callbacks| callbacks.borrow_mut().insert(id, callback));
+    Ok(JsValue::new(id))
+}
 
-    let offset_y = if let Some(arg) = _args.get(1) {
-        arg.as_number().map(|n| n).unwrap_or(0.0) as i32
-    } else {
-        0
-    };
+/// isPaused()：运行是否处于暂停状态
+fn js_is_paused(_this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    abort_check(context)?;
+    Ok(JsValue::Boolean(control::is_paused()))
+}
 
-    let screen_scale_factor = get_backing_scale_factor();
-
-    let windows = Window::all().unwrap();
-    for window in windows {
-        unsafe {
-            if window.title().to_string() == FIND_WINDOW_TITLE {
-                LAST_WINDOW_WIDTH = window.width();
-                LAST_WINDOW_HEIGHT = window.height();
-                let mut enigo = Enigo::new(&Settings::default()).unwrap();
-                if offset_x == 0 && offset_y == 0 {
-                    let center_x = FIND_TEMPLATE_X+LAST_TEMPLATE_WIDTH/2;
-                    let center_y = FIND_TEMPLATE_Y+LAST_TEMPLATE_HEIGHT/2;
-                    info!("图片中心位置: {:?}, {:?}", center_x, center_y);
-                    let click_x= center_x as f32 /screen_scale_factor;
-                    let click_y= center_y as f32 /screen_scale_factor;
-                    info!("点击中心位置: {:?}, {:?}", click_x, click_y);
-                    enigo.move_mouse( window.x() + click_x as i32, window.y() + click_y as i32, Abs).unwrap();
-                }else{
-                    let click_x= offset_x as f32 /screen_scale_factor;
-                    let click_y= offset_y as f32 /screen_scale_factor;
-                    info!("点击位置: {:?}, {:?}", click_x, click_y);
-                    enigo.move_mouse( window.x() + click_x as i32, window.y() + click_y as i32, Abs).unwrap();
-                }
-                thread::sleep(time::Duration::from_millis(500));
-                enigo.button(Button::Left, Click).unwrap();
-                return Ok(JsValue::Boolean(true));
-            }
-        }
+/// onPause / onResume 共用：回调参数检查
+fn hook_arg(args: &[JsValue], name: &str, context: &mut Context) -> JsResult<JsObject> {
+    match args.first().and_then(|arg| arg.as_object()).filter(|object| object.is_callable()) {
+        Some(callback) => Ok(callback),
+        None => Err(context.construct_type_error(format!("{} 需要回调函数", name))),
     }
-    Ok(JsValue::Boolean(false))
 }
 
-fn js_sleep(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
-    let ms = if let Some(arg) = _args.get(0) {
-        arg.as_number().map(|n| n).unwrap_or(0.0)
-    } else {
-        0.0
+/// onPause(fn)：暂停时在阻塞之前调用 fn
+fn js_on_pause(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let callback = hook_arg(args, "onPause", context)?;
+    PAUSE_HOOKS.with(|hooks| hooks.borrow_mut().push(callback));
+    Ok(JsValue::undefined())
+}
+
+/// onResume(fn)：继续运行时调用 fn
+fn js_on_resume(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let callback = hook_arg(args, "onResume", context)?;
+    RESUME_HOOKS.with(|hooks| hooks.borrow_mut().push(callback));
+    Ok(JsValue::undefined())
+}
+
+/// setControlKeys({pause, resume, abort})：修改暂停、继续、中止热键，null 或空数组表示不启用
+fn js_set_control_keys(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let mut keys = control::keys();
+    let Some(object) = args.first().and_then(|arg| arg.as_object()) else {
+        return context.throw_type_error("setControlKeys 需要选项对象");
     };
-    thread::sleep(time::Duration::from_millis(ms as u64));
+    for (name, combo) in [("pause", &mut keys.pause), ("resume", &mut keys.resume), ("abort", &mut keys.abort)] {
+        let value = object.get(name, context)?;
+        if value.is_undefined() {
+            continue;
+        }
+        let disabled = match value.as_object().filter(|list| list.is_array()) {
+            Some(list) => list.get("length", context)?.to_u32(context)? == 0,
+            None => value.is_null(),
+        };
+        *combo = if disabled { Vec::new() } else { hotkey_arg(Some(&value), context)? };
+    }
+    control::set_keys(keys);
     Ok(JsValue::undefined())
 }
 
-fn js_find_x(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+/// removeHotkey(id)：取消 onHotkey 注册的热键
+fn js_remove_hotkey(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let id = args.first().cloned().unwrap_or_default().to_u32(context)?;
+    HOTKEY_CALLBACKS.with(|callbacks| callbacks.borrow_mut().remove(&id));
+    Ok(JsValue::Boolean(hotkeys::unregister(id)))
+}
+
+fn js_find_x(_this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    abort_check(context)?;
     unsafe { Ok(JsValue::Integer(FIND_TEMPLATE_X as i32)) }
 }
 
-fn js_find_y(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+fn js_find_y(_this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    abort_check(context)?;
     unsafe { Ok(JsValue::Integer(FIND_TEMPLATE_Y as i32)) }
 }
 
 
 fn js_window_width(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
     unsafe {
-        let screen_scale_factor = get_backing_scale_factor();
+        let screen_scale_factor = platform::current().scale_factor();
         let mut width = LAST_WINDOW_WIDTH as f32;
         width = width * screen_scale_factor;
         Ok(JsValue::Integer(width as i32))
@@ -511,7 +2195,7 @@ fn js_window_width(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -
 
 fn js_window_height(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
     unsafe {
-        let screen_scale_factor = get_backing_scale_factor();
+        let screen_scale_factor = platform::current().scale_factor();
         let mut width = LAST_WINDOW_HEIGHT as f32;
         width = width * screen_scale_factor;
         Ok(JsValue::Integer(width as i32))
@@ -528,10 +2212,83 @@ fn js_exit(_this: &JsValue, _args: &[JsValue], _context: &mut Context) -> JsResu
     process::exit(exit_code as i32);
 }
 
-fn js_is_key_down(_this: &JsValue, args: &[JsValue], _context: &mut Context) -> JsResult<JsValue> {
+/// hotkey 中相邻两次按键事件之间的间隔
+const KEY_INTERVAL_MS: u64 = 20;
+
+/// 按键名称参数，名称与 isKeyDown 相同，也可以是单个字符
+fn key_arg(arg: Option<&JsValue>, context: &mut Context) -> JsResult<Key> {
+    let Some(name) = arg.and_then(|arg| arg.as_string()).map(|name| name.to_string()) else {
+        return Err(context.construct_type_error("需要按键名称"));
+    };
+    match keys::parse(name.trim()) {
+        Some(key) => Ok(key),
+        None => Err(context.construct_range_error(format!("未知的按键: {}", name))),
+    }
+}
+
+/// keyTap(key)：按下并松开一个键
+fn js_key_tap(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let key = key_arg(args.first(), context)?;
+    Ok(JsValue::Boolean(platform::current().key(key, Click)))
+}
+
+/// keyDown(key)：按下一个键，需要自己调用 keyUp 松开
+fn js_key_down(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let key = key_arg(args.first(), context)?;
+    Ok(JsValue::Boolean(control::send_key(key, Press)))
+}
+
+/// keyUp(key)：松开一个键，中止后在 finally 块中也能调用
+fn js_key_up(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    if !control::is_aborted() {
+        checkpoint(context)?;
+    }
+    let key = key_arg(args.first(), context)?;
+    Ok(JsValue::Boolean(control::send_key(key, Release)))
+}
+
+/// hotkey(key, ...)：依次按下所有键，再按相反顺序松开
+fn js_hotkey(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let keys = args.iter().map(|arg| key_arg(Some(arg), context)).collect::<JsResult<Vec<Key>>>()?;
+    if keys.is_empty() {
+        return context.throw_type_error("hotkey 至少需要一个按键");
+    }
+    // 按下途中失败或被中止时，只松开已经按下的键，不留下卡住的修饰键
+    let mut pressed = 0;
+    let mut ok = true;
+    for key in &keys {
+        if control::is_aborted() || !control::send_key(*key, Press) {
+            ok = false;
+            break;
+        }
+        pressed += 1;
+        thread::sleep(time::Duration::from_millis(KEY_INTERVAL_MS));
+    }
+    for key in keys[..pressed].iter().rev() {
+        ok &= control::send_key(*key, Release);
+        thread::sleep(time::Duration::from_millis(KEY_INTERVAL_MS));
+    }
+    abort_check(context)?;
+    Ok(JsValue::Boolean(ok))
+}
+
+/// typeText(text)：输入一段文本，支持中文等任意 Unicode 字符
+fn js_type_text(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    checkpoint(context)?;
+    let text = match args.first() {
+        Some(arg) => arg.to_string(context)?.to_string(),
+        None => return context.throw_type_error("typeText 需要文本"),
+    };
+    Ok(JsValue::Boolean(platform::current().text(&text)))
+}
+
+fn js_is_key_down(_this: &JsValue, args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    abort_check(context)?;
     let mut result = true;
-    let device_state = DeviceState::new();
-    let mut keys = device_state.get_keys();
+    let keys = platform::current().pressed_keys();
     for i in 0..10 {
         let key_name = if let Some(arg) = args.get(i) {
             arg.as_string()
@@ -549,6 +2306,106 @@ fn js_is_key_down(_this: &JsValue, args: &[JsValue], _context: &mut Context) ->
     unsafe { Ok(JsValue::Boolean(result)) }
 }
 
+fn js_replay_events(_this: &JsValue, _args: &[JsValue], context: &mut Context) -> JsResult<JsValue> {
+    let events = platform::current().recorded_events();
+    js_array(context, events.into_iter().map(JsValue::from).collect())
+}
+
+/// 用给定的元素创建一个 JavaScript 数组
+fn js_array(context: &mut Context, values: Vec<JsValue>) -> JsResult<JsValue> {
+    let array_ctor = context.global_object().get("Array", context)?;
+    let array = match array_ctor.as_object() {
+        Some(ctor) => ctor.construct(&[], &array_ctor, context)?,
+        None => return context.throw_type_error("Array 构造函数不可用"),
+    };
+    if let Some(object) = array.as_object() {
+        for (i, value) in values.into_iter().enumerate() {
+            object.set(i, value, true, context)?;
+        }
+    }
+    Ok(array)
+}
+
+/// 逗号分隔的按键名称，无法识别的名称记录警告后忽略，空字符串得到空列表
+fn parse_key_list(value: &str) -> Vec<Keycode> {
+    let mut keys = Vec::new();
+    for key_name in value.split(',').filter(|name| !name.trim().is_empty()) {
+        match key_name.trim().parse::<Keycode>() {
+            Ok(key_code) => keys.push(key_code),
+            Err(e) => warn!("无法识别的按键名称 {}: {:?}", key_name, e),
+        }
+    }
+    keys
+}
+
+/// 解析脚本路径之后的命令行选项：
+/// 回放模式 --replay <目录或文件>（可重复）、--replay-title <标题>、--replay-scale <缩放系数>、
+/// --replay-record <记录文件>、--replay-keys <按键,按键>；
+/// 运行控制 --pause-keys、--resume-keys、--abort-keys <按键,按键>（空字符串表示不启用）、--start-paused
+fn setup_runtime(options: &[String]) {
+    let mut sources = Vec::new();
+    let mut title = String::from("replay");
+    let mut scale_factor = 1.0;
+    let mut record_file = None;
+    let mut pressed_keys = Vec::new();
+    let (mut pause_keys, mut resume_keys, mut abort_keys) = (None, None, None);
+    let mut iter = options.iter();
+    while let Some(option) = iter.next() {
+        if option == "--start-paused" {
+            control::pause();
+            continue;
+        }
+        let Some(value) = iter.next() else {
+            error!("命令行选项缺少参数: {}", option);
+            process::exit(2);
+        };
+        match option.as_str() {
+            "--replay" => sources.push(value.clone()),
+            "--replay-title" => title = value.clone(),
+            "--replay-scale" => match value.parse::<f32>() {
+                Ok(scale) if scale.is_finite() && scale > 0.0 => scale_factor = scale,
+                _ => {
+                    error!("--replay-scale 需要大于 0 的数值: {}", value);
+                    process::exit(2);
+                }
+            },
+            "--replay-record" => record_file = Some(value.into()),
+            "--replay-keys" => pressed_keys = parse_key_list(value),
+            "--pause-keys" => pause_keys = Some(parse_key_list(value)),
+            "--resume-keys" => resume_keys = Some(parse_key_list(value)),
+            "--abort-keys" => abort_keys = Some(parse_key_list(value)),
+            _ => {
+                error!("未知的命令行选项: {}", option);
+                process::exit(2);
+            }
+        }
+    }
+    // 回放时 --replay-keys 的按键一直按着，可能误触发默认的暂停、中止热键，只使用命令行指定的
+    let mut control_keys = if sources.is_empty() {
+        control::keys()
+    } else {
+        control::ControlKeys { pause: Vec::new(), resume: Vec::new(), abort: Vec::new() }
+    };
+    for (keys, combo) in [(pause_keys, &mut control_keys.pause), (resume_keys, &mut control_keys.resume), (abort_keys, &mut control_keys.abort)] {
+        if let Some(keys) = keys {
+            *combo = keys;
+        }
+    }
+    control::set_keys(control_keys);
+    if sources.is_empty() {
+        return;
+    }
+    match platform::ReplayPlatform::new(&sources, title, scale_factor, record_file, pressed_keys) {
+        Ok(replay) => {
+            platform::set_platform(Box::new(replay));
+        }
+        Err(e) => {
+            error!("{}", e);
+            process::exit(2);
+        }
+    }
+}
+
 fn main() {
 
     // 获取命令行参数
@@ -569,6 +2426,23 @@ fn main() {
         .init();
 
 
+    // 模板匹配性能对比：action --bench-match <截图> <模板> [轮数]
+    if args.get(1).map(|a| a.as_str()) == Some("--bench-match") {
+        if args.len() < 4 {
+            error!("用法: {} --bench-match <截图> <模板> [轮数]", args[0]);
+            process::exit(2);
+        }
+        let rounds = args.get(4).and_then(|n| n.parse().ok()).unwrap_or(3);
+        if let Err(e) = matcher::bench(&args[2], &args[3], rounds) {
+            error!("{}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    // 选择平台后端（带 --replay 时使用离线回放），设置运行控制热键
+    setup_runtime(args.get(2..).unwrap_or(&[]));
+
     // 创建一个新的 JavaScript 上下文
     let mut context = Context::default();
 
@@ -591,6 +2465,14 @@ fn main() {
         js_find_window,
     );
 
+    // 向全局对象添加一个名为 `findWindows` 的函数
+    // 返回所有标题相同的窗口句柄
+    let _ = context.register_global_function(
+        "findWindows",
+        1, // 标题
+        js_find_windows,
+    );
+
     // 向全局对象添加一个名为 `activeWindow` 的函数
     // 激活窗口
     let _ = context.register_global_function(
@@ -607,6 +2489,110 @@ fn main() {
         js_find_template,
     );
 
+    // 向全局对象添加一个名为 `locate` 的函数
+    // 查找图片，返回包含位置、尺寸、中心、匹配率、截图时间和 click() 的匹配对象
+    let _ = context.register_global_function(
+        "locate",
+        1, // 文件名
+        js_locate,
+    );
+
+    // 向全局对象添加一个名为 `snapshot` 的函数
+    // 截图一次，返回的快照对象上的查找共用这一帧
+    let _ = context.register_global_function(
+        "snapshot",
+        0,
+        js_snapshot,
+    );
+
+    // 向全局对象添加一个名为 `findFeature` 的函数
+    // 用特征点匹配查找图片，可以容忍缩放、旋转和部分遮挡
+    let _ = context.register_global_function(
+        "findFeature",
+        1, // 文件名
+        js_find_feature,
+    );
+
+    // 向全局对象添加一个名为 `getPixel` 的函数
+    // 读取窗口中某一点的颜色
+    let _ = context.register_global_function(
+        "getPixel",
+        2, // x, y
+        js_get_pixel,
+    );
+
+    // 向全局对象添加一个名为 `findColor` 的函数
+    // 查找第一个颜色相符的像素
+    let _ = context.register_global_function(
+        "findColor",
+        2, // 颜色, 容差
+        js_find_color,
+    );
+
+    // 向全局对象添加一个名为 `findAllColors` 的函数
+    // 查找所有颜色相符的像素
+    let _ = context.register_global_function(
+        "findAllColors",
+        2, // 颜色, 容差
+        js_find_all_colors,
+    );
+
+    // 向全局对象添加一个名为 `findMultiColor` 的函数
+    // 多点找色，基准点与各偏移点的颜色都相符时返回基准点
+    let _ = context.register_global_function(
+        "findMultiColor",
+        3, // 基准颜色, 偏移点, 容差
+        js_find_multi_color,
+    );
+
+    // 向全局对象添加一个名为 `waitForTemplate` 的函数
+    // 按间隔查找图片，直到出现或超时
+    let _ = context.register_global_function(
+        "waitForTemplate",
+        1, // 文件名
+        js_wait_for_template,
+    );
+
+    // 向全局对象添加一个名为 `waitUntilGone` 的函数
+    // 按间隔查找图片，直到消失或超时
+    let _ = context.register_global_function(
+        "waitUntilGone",
+        1, // 文件名
+        js_wait_until_gone,
+    );
+
+    // 向全局对象添加一个名为 `findAllTemplates` 的函数
+    // 查找所有匹配位置，返回 [{x, y, width, height, score}, ...]
+    let _ = context.register_global_function(
+        "findAllTemplates",
+        1, // 文件名
+        js_find_all_templates,
+    );
+
+    // 向全局对象添加一个名为 `setMatchOptions` 的函数
+    // 设置全局匹配选项
+    let _ = context.register_global_function(
+        "setMatchOptions",
+        1, // 选项对象
+        js_set_match_options,
+    );
+
+    // 向全局对象添加一个名为 `setMouseOptions` 的函数
+    // 设置鼠标移动轨迹、点击偏移和点击节奏
+    let _ = context.register_global_function(
+        "setMouseOptions",
+        1,
+        js_set_mouse_options,
+    );
+
+    // 向全局对象添加一个名为 `preloadTemplates` 的函数
+    // 提前读取并准备模版，之后的查找直接使用内存中的缓存
+    let _ = context.register_global_function(
+        "preloadTemplates",
+        1, // 文件名数组
+        js_preload_templates,
+    );
+
     // 向全局对象添加一个名为 `click` 的函数
     let _ = context.register_global_function(
         "click",
@@ -614,6 +2600,70 @@ fn main() {
         js_click,
     );
 
+    // 向全局对象添加一个名为 `rightClick` 的函数
+    // 右键点击，坐标规则与 click 相同
+    let _ = context.register_global_function(
+        "rightClick",
+        2,
+        js_right_click,
+    );
+
+    // 向全局对象添加一个名为 `middleClick` 的函数
+    // 中键点击
+    let _ = context.register_global_function(
+        "middleClick",
+        2,
+        js_middle_click,
+    );
+
+    // 向全局对象添加一个名为 `doubleClick` 的函数
+    // 双击
+    let _ = context.register_global_function(
+        "doubleClick",
+        3,
+        js_double_click,
+    );
+
+    // 向全局对象添加一个名为 `mouseDown` 的函数
+    // 按下鼠标按键，可以先移动到指定坐标
+    let _ = context.register_global_function(
+        "mouseDown",
+        3,
+        js_mouse_down,
+    );
+
+    // 向全局对象添加一个名为 `mouseUp` 的函数
+    // 松开鼠标按键，可以先移动到指定坐标
+    let _ = context.register_global_function(
+        "mouseUp",
+        3,
+        js_mouse_up,
+    );
+
+    // 向全局对象添加一个名为 `moveTo` 的函数
+    // 只移动鼠标，不点击
+    let _ = context.register_global_function(
+        "moveTo",
+        2,
+        js_move_to,
+    );
+
+    // 向全局对象添加一个名为 `drag` 的函数
+    // 按住鼠标从起点拖动到终点
+    let _ = context.register_global_function(
+        "drag",
+        5,
+        js_drag,
+    );
+
+    // 向全局对象添加一个名为 `scroll` 的函数
+    // 滚动鼠标滚轮
+    let _ = context.register_global_function(
+        "scroll",
+        2,
+        js_scroll,
+    );
+
     // 向全局对象添加一个名为 `sleep` 的函数
     let _ = context.register_global_function(
         "sleep",
@@ -669,12 +2719,240 @@ fn main() {
         js_is_key_down,
     );
 
+    // 向全局对象添加一个名为 `onHotkey` 的函数
+    // 注册热键回调，在 sleep 和等待函数中执行
+    let _ = context.register_global_function(
+        "onHotkey",
+        2,
+        js_on_hotkey,
+    );
+
+    // 向全局对象添加一个名为 `removeHotkey` 的函数
+    // 取消热键
+    let _ = context.register_global_function(
+        "removeHotkey",
+        1,
+        js_remove_hotkey,
+    );
+
+    // 向全局对象添加一个名为 `isPaused` 的函数
+    // 是否处于暂停状态
+    let _ = context.register_global_function(
+        "isPaused",
+        0,
+        js_is_paused,
+    );
+
+    // 向全局对象添加一个名为 `onPause` 的函数
+    // 注册暂停时的回调
+    let _ = context.register_global_function(
+        "onPause",
+        1,
+        js_on_pause,
+    );
+
+    // 向全局对象添加一个名为 `onResume` 的函数
+    // 注册继续运行时的回调
+    let _ = context.register_global_function(
+        "onResume",
+        1,
+        js_on_resume,
+    );
+
+    // 向全局对象添加一个名为 `setControlKeys` 的函数
+    // 修改暂停、继续、中止热键
+    let _ = context.register_global_function(
+        "setControlKeys",
+        1,
+        js_set_control_keys,
+    );
+
+    // 向全局对象添加一个名为 `keyTap` 的函数
+    // 按下并松开一个键
+    let _ = context.register_global_function(
+        "keyTap",
+        1,
+        js_key_tap,
+    );
+
+    // 向全局对象添加一个名为 `keyDown` 的函数
+    // 按下一个键
+    let _ = context.register_global_function(
+        "keyDown",
+        1,
+        js_key_down,
+    );
+
+    // 向全局对象添加一个名为 `keyUp` 的函数
+    // 松开一个键
+    let _ = context.register_global_function(
+        "keyUp",
+        1,
+        js_key_up,
+    );
+
+    // 向全局对象添加一个名为 `hotkey` 的函数
+    // 按下组合键，例如 hotkey("LControl", "S")
+    let _ = context.register_global_function(
+        "hotkey",
+        1,
+        js_hotkey,
+    );
+
+    // 向全局对象添加一个名为 `typeText` 的函数
+    // 输入一段文本
+    let _ = context.register_global_function(
+        "typeText",
+        1,
+        js_type_text,
+    );
+
+    // 向全局对象添加一个名为 `replayEvents` 的函数
+    // 用于返回回放模式下记录的输入事件
+    let _ = context.register_global_function(
+        "replayEvents",
+        0, // 无需参数
+        js_replay_events,
+    );
+
     // 加载脚本
     let source = fs::read_to_string(args[1].to_string()).unwrap();
 
+    // 后台检测热键，暂停、继续、中止热键从脚本开始时就生效
+    hotkeys::start();
+
     // 执行代码
     let start = Instant::now();
-    context.eval(source).unwrap();
+    let result = context.eval(source);
+    if control::is_aborted() {
+        // finally 块没有松开的键和鼠标按键由这里松开
+        control::release_held();
+        info!("脚本已中止");
+    } else {
+        result.unwrap();
+    }
     println!("运行耗时: {:?}", start.elapsed());
 
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 固定种子的伪随机 RGB 图
+    fn noise(width: u32, height: u32, seed: u64) -> RgbaImage {
+        let mut state = seed;
+        RgbaImage::from_fn(width, height, |_, _| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let v = (state >> 40) as u32;
+            image::Rgba([(v >> 16) as u8, (v >> 8) as u8, v as u8, 255])
+        })
+    }
+
+    /// 把模板保存为临时文件，模板缓存按路径区分，每个测试使用不同的名称
+    fn template_file(name: &str, template: &RgbaImage) -> String {
+        let path = env::temp_dir().join(format!("action-test-{}-{}.png", process::id(), name));
+        template.save(&path).unwrap();
+        path.to_str().unwrap().to_string()
+    }
+
+    fn frame(image: RgbaImage) -> Frame {
+        Frame { window_id: 1, image, timestamp: 0.0 }
+    }
+
+    #[test]
+    fn preprocessing_applies_to_capture_and_template() {
+        let template = noise(24, 24, 2);
+        let mut scene = noise(160, 120, 1);
+        image::imageops::replace(&mut scene, &template, 70, 40);
+        let file = template_file("preprocess", &template);
+        let options = MatchOptions {
+            preprocess: vec![preprocess::Step::Contrast(1.5), preprocess::Step::Grayscale, preprocess::Step::Threshold(Some(128))],
+            ..MatchOptions::default()
+        };
+        let found = locate_in_frame(&frame(scene.clone()), &file, None, &options).unwrap();
+        assert_eq!((found.x, found.y, found.width, found.height), (70, 40, 24, 24));
+        assert!(found.score > 0.999, "{}", found.score);
+        // 只处理截图、不处理模板时得分明显更低
+        let processed = preprocess::apply(&DynamicImage::ImageRgba8(scene), &options.preprocess);
+        let (_, _, raw_score) = matcher::match_template(&processed, &DynamicImage::ImageRgba8(template), None, &MatchOptions::default()).unwrap();
+        assert!(raw_score < 0.95, "{}", raw_score);
+    }
+
+    #[test]
+    fn rotation_search_reports_angle() {
+        let template = DynamicImage::ImageRgba8(noise(24, 24, 5));
+        let (rotated, mask) = rotate_template(&template, None, 25.0);
+        let mut scene = noise(160, 120, 6);
+        for (x, y, pixel) in rotated.to_rgba8().enumerate_pixels() {
+            if mask.get_pixel(x, y)[0] >= 128 {
+                scene.put_pixel(60 + x, 40 + y, *pixel);
+            }
+        }
+        let file = template_file("rotation", &template.to_rgba8());
+        let options = MatchOptions { angle_range: Some((-30.0, 30.0)), angle_step: 10.0, ..MatchOptions::default() };
+        let found = match_best_transform(&file, &DynamicImage::ImageRgba8(scene), &Arc::new(template), &options).unwrap();
+        assert!((found.angle - 25.0).abs() <= 2.5, "{}", found.angle);
+        assert!(found.x.abs_diff(60) <= 1 && found.y.abs_diff(40) <= 1, "({}, {})", found.x, found.y);
+    }
+
+    #[test]
+    fn find_any_returns_first_in_list_order() {
+        let (a, b) = (noise(20, 20, 7), noise(20, 20, 8));
+        let mut scene = noise(160, 120, 9);
+        image::imageops::replace(&mut scene, &a, 20, 20);
+        image::imageops::replace(&mut scene, &b, 100, 60);
+        let frame = frame(scene);
+        let (a, b) = (template_file("any-a", &a), template_file("any-b", &b));
+        let missing = env::temp_dir().join("action-test-missing.png").to_str().unwrap().to_string();
+        let list = |files: &[&String]| files.iter().map(|file| (file.to_string(), None)).collect::<Vec<_>>();
+        let options = MatchOptions::default();
+        let (index, found) = find_any(&frame, &list(&[&missing, &b, &a]), 0.8, &options).unwrap();
+        assert_eq!((index, found.x, found.y), (1, 100, 60));
+        let (index, found) = find_any(&frame, &list(&[&a, &b]), 0.8, &options).unwrap();
+        assert_eq!((index, found.x, found.y), (0, 20, 20));
+        // 区域内没有 A 时跳过它
+        let regions = vec![(a.clone(), Some((80, 40, 60, 60))), (b.clone(), None)];
+        assert_eq!(find_any(&frame, &regions, 0.8, &options).map(|(index, _)| index), Some(1));
+        assert!(find_any(&frame, &list(&[&missing]), 0.8, &options).is_none());
+    }
+
+    #[test]
+    fn tracking_is_per_window_and_margin_saturates() {
+        let template = noise(16, 16, 10);
+        let file = template_file("track", &template);
+        let options = MatchOptions { track: true, track_margin: u32::MAX, ..MatchOptions::default() };
+        let mut first = noise(120, 90, 11);
+        image::imageops::replace(&mut first, &template, 30, 20);
+        let mut second = noise(120, 90, 12);
+        image::imageops::replace(&mut second, &template, 80, 60);
+        let second = Frame { window_id: 2, ..frame(second) };
+        for _ in 0..2 {
+            let found = locate_in_frame(&frame(first.clone()), &file, None, &options).unwrap();
+            assert_eq!((found.x, found.y), (30, 20));
+            let found = locate_in_frame(&second, &file, None, &options).unwrap();
+            assert_eq!((found.x, found.y), (80, 60));
+        }
+        let positions = TEMPLATE_POSITIONS.lock().unwrap();
+        assert_eq!(positions.get(&(1, file.clone())), Some(&(30, 20, 16, 16)));
+        assert_eq!(positions.get(&(2, file.clone())), Some(&(80, 60, 16, 16)));
+    }
+
+    #[test]
+    fn multi_scale_recovers_scale_and_caches_it() {
+        let template = noise(20, 16, 3);
+        let scaled = DynamicImage::ImageRgba8(template.clone()).resize_exact(30, 24, FilterType::Triangle).to_rgba8();
+        let mut scene = noise(160, 120, 4);
+        image::imageops::replace(&mut scene, &scaled, 90, 50);
+        let file = template_file("multi-scale", &template);
+        let scene = DynamicImage::ImageRgba8(scene);
+        let template_image = Arc::new(DynamicImage::ImageRgba8(template));
+        let options = MatchOptions { scale_range: Some((0.8, 2.0)), scale_step: 0.1, ..MatchOptions::default() };
+        let found = match_best_transform(&file, &scene, &template_image, &options).unwrap();
+        assert_eq!((found.x, found.y, found.scale), (90, 50, 1.5));
+        assert_eq!(TEMPLATE_TRANSFORMS.lock().unwrap().get(&file).copied(), Some((1.5, 0.0)));
+        // 步长大到只会尝试 0.8 和 2.0，仍然得到 1.5 说明使用了缓存
+        let options = MatchOptions { scale_step: 5.0, ..options };
+        let found = match_best_transform(&file, &scene, &template_image, &options).unwrap();
+        assert_eq!((found.x, found.y, found.scale), (90, 50, 1.5));
+    }
+}
@@ -0,0 +1,1380 @@
+//! 模板匹配
+//!
+//! 归一化互相关 (NCC) 的定义与原来的逐像素实现一致：
+//! `ncc = Σ(I - μI)(T - μT) / (n · σI · σT)`，σ 为总体标准差，任一标准差为 0 时记为 0。
+//!
+//! 由于 `Σ(I - μI)(T - μT) = Σ I · (T - μT)`，分子只是图像与去均值模板的互相关，
+//! 分母中的 μI、σI 则用积分图 (summed-area table) 在 O(1) 内求出。
+//! 互相关按代价在两种实现之间选择：模板较小时直接在连续内存上计算，
+//! 模板较大时用 FFT 一次算出所有位置。
+//!
+//! 带掩码的模板只统计掩码内的像素：μT、σT 只在掩码内计算，去均值模板在掩码外为 0，
+//! 图像一侧的像素和与平方和则改为图像（及其平方）与掩码的互相关。
+
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use image::{DynamicImage, GenericImageView, GrayImage, ImageBuffer, Luma, Rgba, RgbaImage};
+use imageproc::geometric_transformations::{rotate_about_center, Interpolation};
+use log::{error, info};
+use rayon::prelude::*;
+use rustfft::num_complex::Complex;
+use rustfft::FftPlanner;
+
+use crate::preprocess::Step;
+
+/// 灰度图的积分图，保存像素和与像素平方和，均为精确整数
+struct IntegralImage {
+    stride: usize,
+    sum: Vec<u64>,
+    sq_sum: Vec<u64>,
+}
+
+impl IntegralImage {
+    fn new(img: &GrayImage) -> Self {
+        let (width, height) = (img.width() as usize, img.height() as usize);
+        let stride = width + 1;
+        let mut sum = vec![0u64; stride * (height + 1)];
+        let mut sq_sum = vec![0u64; stride * (height + 1)];
+        let raw = img.as_raw();
+        for y in 0..height {
+            let mut row_sum = 0u64;
+            let mut row_sq_sum = 0u64;
+            for x in 0..width {
+                let v = raw[y * width + x] as u64;
+                row_sum += v;
+                row_sq_sum += v * v;
+                sum[(y + 1) * stride + x + 1] = sum[y * stride + x + 1] + row_sum;
+                sq_sum[(y + 1) * stride + x + 1] = sq_sum[y * stride + x + 1] + row_sq_sum;
+            }
+        }
+        IntegralImage { stride, sum, sq_sum }
+    }
+
+    /// 区域 (x, y, w, h) 的像素和与平方和
+    fn region(&self, x: usize, y: usize, w: usize, h: usize) -> (u64, u64) {
+        let s = self.stride;
+        let (a, b, c, d) = (y * s + x, y * s + x + w, (y + h) * s + x, (y + h) * s + x + w);
+        (
+            self.sum[d] + self.sum[a] - self.sum[b] - self.sum[c],
+            self.sq_sum[d] + self.sq_sum[a] - self.sq_sum[b] - self.sq_sum[c],
+        )
+    }
+}
+
+/// 去均值后的模板及其统计量
+struct PreparedTemplate {
+    width: usize,
+    height: usize,
+    /// T - μT，按行存放；有掩码时掩码外为 0
+    centered: Vec<f32>,
+    /// 参与计算的像素均值
+    mean: f64,
+    stddev: f64,
+    /// 掩码，1 表示参与计算；None 表示所有像素都参与
+    mask: Option<Vec<f32>>,
+    /// 参与计算的像素数
+    count: usize,
+}
+
+impl PreparedTemplate {
+    fn new(template: &GrayImage, mask: Option<&GrayImage>) -> Self {
+        let (width, height) = (template.width() as usize, template.height() as usize);
+        let raw = template.as_raw();
+        let mask: Option<Vec<f32>> = mask.map(|mask| {
+            mask.as_raw().iter().map(|&m| if m >= MASK_LEVEL { 1.0 } else { 0.0 }).collect()
+        });
+        let included = |i: usize| mask.as_ref().map_or(true, |mask| mask[i] > 0.0);
+        let count = (0..raw.len()).filter(|&i| included(i)).count();
+        let size = count.max(1) as f64;
+        let mean = (0..raw.len()).filter(|&i| included(i)).map(|i| raw[i] as f64).sum::<f64>() / size;
+        let variance = (0..raw.len()).filter(|&i| included(i)).map(|i| (raw[i] as f64 - mean).powi(2)).sum::<f64>() / size;
+        PreparedTemplate {
+            width,
+            height,
+            centered: (0..raw.len()).map(|i| if included(i) { (raw[i] as f64 - mean) as f32 } else { 0.0 }).collect(),
+            mean,
+            stddev: variance.sqrt(),
+            mask,
+            count,
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+/// 掩码图中不小于该值的像素参与匹配
+const MASK_LEVEL: u8 = 128;
+
+/// 图像一侧每个匹配位置的像素和与平方和
+enum ImageStats<'a> {
+    /// 无掩码：用积分图求矩形区域
+    Integral(IntegralImage),
+    /// 有掩码：用 FFT 预先算好每个位置掩码内的值，行优先，宽为 `img_width - template_width + 1`
+    Masked { width: usize, sum: Vec<u64>, sq_sum: Vec<u64> },
+    /// 有掩码但只需要少量位置：按需直接计算
+    MaskedDirect(&'a GrayImage),
+}
+
+impl<'a> ImageStats<'a> {
+    /// 需要大部分位置时使用，掩码模板在适合 FFT 时一次算出全部位置
+    fn new(img: &'a GrayImage, template: &PreparedTemplate) -> Self {
+        let Some(mask) = &template.mask else {
+            return ImageStats::Integral(IntegralImage::new(img));
+        };
+        let (img_width, img_height) = (img.width() as usize, img.height() as usize);
+        if !prefer_fft(img_width, img_height, template) {
+            return ImageStats::MaskedDirect(img);
+        }
+        // 像素和都是整数，四舍五入即可消除 FFT 的舍入误差
+        let round = |values: Vec<f64>| values.into_iter().map(|v| v.round().max(0.0) as u64).collect::<Vec<u64>>();
+        ImageStats::Masked {
+            width: img_width - template.width + 1,
+            sum: round(correlate_fft_kernel(img, |v| v, mask, template.width)),
+            sq_sum: round(correlate_fft_kernel(img, |v| v * v, mask, template.width)),
+        }
+    }
+
+    /// 只需要少量位置时使用
+    fn sparse(img: &'a GrayImage, template: &PreparedTemplate) -> Self {
+        match template.mask {
+            Some(_) => ImageStats::MaskedDirect(img),
+            None => ImageStats::Integral(IntegralImage::new(img)),
+        }
+    }
+
+    /// 位置 (x, y) 处参与计算的像素和与平方和
+    fn region(&self, x: usize, y: usize, template: &PreparedTemplate) -> (u64, u64) {
+        match self {
+            ImageStats::Integral(integral) => integral.region(x, y, template.width, template.height),
+            ImageStats::Masked { width, sum, sq_sum } => (sum[y * width + x], sq_sum[y * width + x]),
+            ImageStats::MaskedDirect(img) => {
+                let img_width = img.width() as usize;
+                let raw = img.as_raw();
+                let mask = template.mask.as_deref().unwrap_or_default();
+                let (mut sum, mut sq_sum) = (0u64, 0u64);
+                for ty in 0..template.height {
+                    let start = (y + ty) * img_width + x;
+                    let img_row = &raw[start..start + template.width];
+                    let mask_row = &mask[ty * template.width..(ty + 1) * template.width];
+                    for (&v, &m) in img_row.iter().zip(mask_row) {
+                        if m > 0.0 {
+                            sum += v as u64;
+                            sq_sum += v as u64 * v as u64;
+                        }
+                    }
+                }
+                (sum, sq_sum)
+            }
+        }
+    }
+}
+
+/// 由互相关值和图像统计量得到 NCC
+fn ncc_from_correlation(
+    correlation: f64,
+    stats: &ImageStats,
+    template: &PreparedTemplate,
+    x: usize,
+    y: usize,
+) -> f32 {
+    let n = template.count as u64;
+    let (sum, sq_sum) = stats.region(x, y, template);
+    // n²·σI² = n·ΣI² - (ΣI)²，整数运算保证常量区域的方差严格为 0
+    let scaled_variance = n * sq_sum - sum * sum;
+    if scaled_variance == 0 || template.stddev == 0.0 {
+        return 0.0;
+    }
+    (correlation / ((scaled_variance as f64).sqrt() * template.stddev)) as f32
+}
+
+/// 直接计算 (x, y) 处图像与去均值模板的互相关
+fn correlate_at(img: &GrayImage, template: &PreparedTemplate, x: usize, y: usize) -> f64 {
+    let img_width = img.width() as usize;
+    let raw = img.as_raw();
+    let mut total = 0.0f64;
+    for ty in 0..template.height {
+        let start = (y + ty) * img_width + x;
+        let img_row = &raw[start..start + template.width];
+        let template_row = &template.centered[ty * template.width..(ty + 1) * template.width];
+        let row: f32 = img_row.iter().zip(template_row).map(|(&i, &t)| i as f32 * t).sum();
+        total += row as f64;
+    }
+    total
+}
+
+/// 选择 FFT 的尺寸：不小于 n 且只含 2、3、5 因子，FFT 在这类尺寸上最快
+fn fft_size(n: usize) -> usize {
+    let mut size = n.max(1);
+    loop {
+        let mut m = size;
+        for p in [2, 3, 5] {
+            while m % p == 0 {
+                m /= p;
+            }
+        }
+        if m == 1 {
+            return size;
+        }
+        size += 1;
+    }
+}
+
+/// 转置 width × height 的矩阵
+fn transpose(data: &[Complex<f64>], width: usize, height: usize) -> Vec<Complex<f64>> {
+    let mut out = vec![Complex::new(0.0, 0.0); data.len()];
+    out.par_chunks_mut(height).enumerate().for_each(|(x, column)| {
+        for (y, value) in column.iter_mut().enumerate() {
+            *value = data[y * width + x];
+        }
+    });
+    out
+}
+
+/// 二维 FFT：先对每行做 FFT，转置后再对每列做 FFT，结果为转置布局 (height × width)
+fn fft_2d(planner: &mut FftPlanner<f64>, data: Vec<Complex<f64>>, width: usize, height: usize, inverse: bool) -> Vec<Complex<f64>> {
+    let (row_fft, column_fft) = if inverse {
+        (planner.plan_fft_inverse(width), planner.plan_fft_inverse(height))
+    } else {
+        (planner.plan_fft_forward(width), planner.plan_fft_forward(height))
+    };
+    let mut data = data;
+    data.par_chunks_mut(width).for_each(|row| row_fft.process(row));
+    let mut transposed = transpose(&data, width, height);
+    transposed.par_chunks_mut(height).for_each(|column| column_fft.process(column));
+    transposed
+}
+
+/// 用 FFT 计算所有有效位置的互相关，返回行优先、宽为 `img_width - template_width + 1` 的结果
+fn correlate_fft(img: &GrayImage, template: &PreparedTemplate) -> Vec<f64> {
+    correlate_fft_kernel(img, |v| v, &template.centered, template.width)
+}
+
+/// 用 FFT 计算 pixel(I) 与 kernel 在所有有效位置的互相关，kernel 按行存放、宽为 kernel_width
+fn correlate_fft_kernel(img: &GrayImage, pixel: impl Fn(f64) -> f64, kernel: &[f32], kernel_width: usize) -> Vec<f64> {
+    let (img_width, img_height) = (img.width() as usize, img.height() as usize);
+    let kernel_height = kernel.len() / kernel_width;
+    // 只需要有效位置，循环卷积的回绕不会落到有效区域，所以 FFT 尺寸不小于图像即可
+    let (fw, fh) = (fft_size(img_width), fft_size(img_height));
+    let mut planner = FftPlanner::new();
+
+    let mut img_data = vec![Complex::new(0.0, 0.0); fw * fh];
+    for (y, row) in img.as_raw().chunks(img_width).enumerate() {
+        for (x, &v) in row.iter().enumerate() {
+            img_data[y * fw + x] = Complex::new(pixel(v as f64), 0.0);
+        }
+    }
+    let mut kernel_data = vec![Complex::new(0.0, 0.0); fw * fh];
+    for (y, row) in kernel.chunks(kernel_width).enumerate() {
+        for (x, &v) in row.iter().enumerate() {
+            kernel_data[y * fw + x] = Complex::new(v as f64, 0.0);
+        }
+    }
+
+    let img_spectrum = fft_2d(&mut planner, img_data, fw, fh, false);
+    let kernel_spectrum = fft_2d(&mut planner, kernel_data, fw, fh, false);
+    // 互相关 = IFFT(F(I) · conj(F(T)))，频域数据是转置布局，宽高互换
+    let product: Vec<Complex<f64>> = img_spectrum
+        .par_iter()
+        .zip(kernel_spectrum.par_iter())
+        .map(|(i, t)| i * t.conj())
+        .collect();
+    let correlation = fft_2d(&mut planner, product, fh, fw, true);
+
+    let scale = (fw * fh) as f64;
+    let out_width = img_width - kernel_width + 1;
+    let out_height = img_height - kernel_height + 1;
+    let mut out = vec![0.0; out_width * out_height];
+    for y in 0..out_height {
+        for x in 0..out_width {
+            out[y * out_width + x] = correlation[y * fw + x].re / scale;
+        }
+    }
+    out
+}
+
+/// 是否使用 FFT：按直接计算与 FFT 的大致运算量比较
+fn prefer_fft(img_width: usize, img_height: usize, template: &PreparedTemplate) -> bool {
+    let positions = (img_width - template.width + 1) * (img_height - template.height + 1);
+    let direct_cost = (positions * template.size()) as f64;
+    let n = (fft_size(img_width) * fft_size(img_height)) as f64;
+    // 三次二维 FFT 加上转置，实测耗时约相当于 8·N·log2(N) 次直接乘加
+    let fft_cost = 8.0 * n * n.log2();
+    direct_cost > fft_cost
+}
+
+/// 匹配选项，脚本可以全局设置 (setMatchOptions) 或在单次 findTemplate 中覆盖
+#[derive(Debug, Clone)]
+pub struct MatchOptions {
+    /// 金字塔模式：先在缩小的图像上粗匹配，再在原分辨率下只细化候选位置附近
+    pub pyramid: bool,
+    /// 金字塔层数，每层缩小一半；0 表示按模板尺寸自动选择
+    pub pyramid_levels: u32,
+    /// 匹配前对截图和模板依次应用的预处理步骤
+    pub preprocess: Vec<Step>,
+    /// 模板掩码的来源
+    pub mask: MaskSource,
+    /// 匹配使用的颜色空间
+    pub color: ColorMode,
+    /// 平均颜色与模板的最大距离（所选颜色空间中的欧氏距离，Lab 下即 ΔE），None 表示不检查
+    pub color_tolerance: Option<f32>,
+    /// 多尺度匹配时模板的缩放范围 (最小, 最大)，None 表示只用模板原尺寸
+    pub scale_range: Option<(f32, f32)>,
+    /// 多尺度匹配的缩放步长
+    pub scale_step: f32,
+    /// 旋转匹配时模板的角度范围 (最小, 最大)，单位为度，顺时针为正；None 表示不旋转
+    pub angle_range: Option<(f32, f32)>,
+    /// 旋转匹配的角度步长
+    pub angle_step: f32,
+    /// 多个位置都能匹配时返回哪一个
+    pub search: SearchPolicy,
+    /// 跟踪模式：先在该模板上次匹配位置附近搜索，得分低于 track_threshold 时再搜索整个区域
+    pub track: bool,
+    /// 跟踪模式下在上次匹配位置四周扩展的像素数
+    pub track_margin: u32,
+    /// 跟踪模式下局部搜索结果的最低得分
+    pub track_threshold: f32,
+}
+
+/// 匹配结果的选取方式，两种方式的结果都与线程数、调度顺序无关
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SearchPolicy {
+    /// 全局得分最高的位置，得分相同时取扫描顺序靠前的
+    Best,
+    /// 扫描顺序（从上到下、从左到右）中第一个得分超过阈值的位置，没有时退回全局最佳。
+    /// 某一行找到后，更靠后的行不再计算
+    First(f32),
+}
+
+impl SearchPolicy {
+    /// First 的默认阈值
+    pub const DEFAULT_FIRST_THRESHOLD: f32 = 0.95;
+
+    fn first_threshold(self) -> Option<f32> {
+        match self {
+            SearchPolicy::Best => None,
+            SearchPolicy::First(threshold) => Some(threshold),
+        }
+    }
+}
+
+/// 模板掩码的来源
+#[derive(Debug, Clone, PartialEq)]
+pub enum MaskSource {
+    /// 使用模板自身的 alpha 通道（默认），模板不透明时不使用掩码
+    Alpha,
+    /// 使用单独的掩码图
+    File(String),
+    /// 不使用掩码，所有像素都参与匹配
+    Off,
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        MatchOptions {
+            pyramid: false,
+            pyramid_levels: 0,
+            preprocess: Vec::new(),
+            mask: MaskSource::Alpha,
+            color: ColorMode::Gray,
+            color_tolerance: None,
+            scale_range: None,
+            scale_step: 0.1,
+            angle_range: None,
+            angle_step: 10.0,
+            search: SearchPolicy::Best,
+            track: false,
+            track_margin: 32,
+            track_threshold: 0.8,
+        }
+    }
+}
+
+/// 金字塔模式下粗匹配保留的候选数量
+const PYRAMID_CANDIDATES: usize = 5;
+/// 自动选择层数时，缩小后的模板短边不小于该值
+const PYRAMID_MIN_TEMPLATE_SIDE: u32 = 12;
+/// 金字塔层数的上限，自动选择与脚本指定的层数都不超过该值
+pub const PYRAMID_MAX_LEVELS: u32 = 4;
+
+/// 计算所有位置的 NCC，返回行优先、宽为 `img_width - template_width + 1` 的得分图
+fn score_map(img: &GrayImage, template: &PreparedTemplate, stats: &ImageStats) -> Vec<f32> {
+    let (img_width, img_height) = (img.width() as usize, img.height() as usize);
+    let out_width = img_width - template.width + 1;
+    let out_height = img_height - template.height + 1;
+    let mut scores = vec![0.0f32; out_width * out_height];
+    if prefer_fft(img_width, img_height, template) {
+        let correlation = correlate_fft(img, template);
+        scores.par_chunks_mut(out_width).enumerate().for_each(|(y, row)| {
+            for (x, score) in row.iter_mut().enumerate() {
+                *score = ncc_from_correlation(correlation[y * out_width + x], stats, template, x, y);
+            }
+        });
+    } else {
+        scores.par_chunks_mut(out_width).enumerate().for_each(|(y, row)| {
+            for (x, score) in row.iter_mut().enumerate() {
+                *score = ncc_from_correlation(correlate_at(img, template, x, y), stats, template, x, y);
+            }
+        });
+    }
+    scores
+}
+
+/// 按 factor × factor 的块求平均缩小灰度图，边缘不足一块的部分舍弃
+fn downscale(img: &GrayImage, factor: u32) -> GrayImage {
+    let (width, height) = (img.width() / factor, img.height() / factor);
+    let area = factor * factor;
+    GrayImage::from_fn(width, height, |x, y| {
+        let mut sum = 0u32;
+        for dy in 0..factor {
+            for dx in 0..factor {
+                sum += img.get_pixel(x * factor + dx, y * factor + dy)[0] as u32;
+            }
+        }
+        Luma([((sum + area / 2) / area) as u8])
+    })
+}
+
+/// 金字塔层数：指定时直接使用（不超过 PYRAMID_MAX_LEVELS），否则按模板短边自动选择
+fn pyramid_levels(template: &GrayImage, options: &MatchOptions) -> u32 {
+    if options.pyramid_levels > 0 {
+        return options.pyramid_levels.min(PYRAMID_MAX_LEVELS);
+    }
+    let min_side = template.width().min(template.height());
+    let mut levels = 0;
+    while levels < PYRAMID_MAX_LEVELS && min_side >> (levels + 1) >= PYRAMID_MIN_TEMPLATE_SIDE {
+        levels += 1;
+    }
+    levels
+}
+
+/// 取得分图中的局部最大值（3×3 邻域），按得分从高到低返回前 count 个
+fn top_candidates(scores: &[f32], width: usize, height: usize, count: usize) -> Vec<(usize, usize)> {
+    let mut peaks: Vec<(usize, usize, f32)> = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let score = scores[y * width + x];
+            let mut is_peak = true;
+            'neighbours: for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    if scores[ny * width + nx] > score {
+                        is_peak = false;
+                        break 'neighbours;
+                    }
+                }
+            }
+            if is_peak {
+                peaks.push((x, y, score));
+            }
+        }
+    }
+    peaks.sort_by(|a, b| b.2.total_cmp(&a.2).then(a.1.cmp(&b.1)).then(a.0.cmp(&b.0)));
+    peaks.into_iter().take(count).map(|(x, y, _)| (x, y)).collect()
+}
+
+/// 匹配使用的颜色空间
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorMode {
+    /// 灰度 NCC（默认）
+    Gray,
+    /// R、G、B 三个通道分别计算 NCC 后取平均
+    Rgb,
+    /// 转换到 CIE Lab 后三个通道分别计算 NCC 后取平均
+    Lab,
+}
+
+impl ColorMode {
+    pub fn parse(name: &str) -> Option<ColorMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "gray" | "grey" => Some(ColorMode::Gray),
+            "rgb" => Some(ColorMode::Rgb),
+            "lab" => Some(ColorMode::Lab),
+            _ => None,
+        }
+    }
+
+    /// 按颜色空间把图像拆分为若干 8 位通道
+    fn split(self, image: &DynamicImage) -> Vec<GrayImage> {
+        match self {
+            ColorMode::Gray => vec![image.to_luma8()],
+            ColorMode::Rgb | ColorMode::Lab => {
+                let rgb = image.to_rgb8();
+                let (width, height) = rgb.dimensions();
+                let mut channels = vec![GrayImage::new(width, height); 3];
+                for (x, y, pixel) in rgb.enumerate_pixels() {
+                    let values = match self {
+                        ColorMode::Lab => encode_lab(srgb_to_lab(pixel.0)),
+                        _ => pixel.0,
+                    };
+                    for (channel, value) in channels.iter_mut().zip(values) {
+                        channel.put_pixel(x, y, Luma([value]));
+                    }
+                }
+                channels
+            }
+        }
+    }
+
+    /// 把各通道的均值换算回该颜色空间的坐标，用于计算平均颜色的距离
+    fn decode(self, means: &[f64]) -> Vec<f64> {
+        match self {
+            ColorMode::Lab => vec![means[0] * 100.0 / 255.0, means[1] - 128.0, means[2] - 128.0],
+            _ => means.to_vec(),
+        }
+    }
+}
+
+/// sRGB (0-255) 转 CIE Lab，D65 白点
+fn srgb_to_lab(rgb: [u8; 3]) -> [f64; 3] {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    let (r, g, b) = (linear(rgb[0]), linear(rgb[1]), linear(rgb[2]));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f64| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Lab 编码为 8 位：L 缩放到 0-255，a、b 偏移 128
+fn encode_lab(lab: [f64; 3]) -> [u8; 3] {
+    [
+        (lab[0] * 255.0 / 100.0).round().clamp(0.0, 255.0) as u8,
+        (lab[1] + 128.0).round().clamp(0.0, 255.0) as u8,
+        (lab[2] + 128.0).round().clamp(0.0, 255.0) as u8,
+    ]
+}
+
+/// 多通道打分：每个通道分别计算 NCC 后取平均，模板在某个通道上没有变化时该通道不参与平均。
+/// 设置了颜色容差时，平均颜色与模板相差超过容差的位置得分记为 0
+struct Scorer<'a> {
+    images: &'a [GrayImage],
+    templates: &'a [PreparedTemplate],
+    stats: Vec<ImageStats<'a>>,
+    mode: ColorMode,
+    /// 颜色容差与模板的平均颜色
+    color_check: Option<(f32, Vec<f64>)>,
+}
+
+impl<'a> Scorer<'a> {
+    /// sparse 为 true 时只会对少量位置打分
+    fn new(images: &'a [GrayImage], templates: &'a [PreparedTemplate], mode: ColorMode, tolerance: Option<f32>, sparse: bool) -> Self {
+        let stats = images
+            .iter()
+            .zip(templates)
+            .map(|(img, template)| if sparse { ImageStats::sparse(img, template) } else { ImageStats::new(img, template) })
+            .collect();
+        let color_check = tolerance.map(|tolerance| {
+            let means: Vec<f64> = templates.iter().map(|t| t.mean).collect();
+            (tolerance, mode.decode(&means))
+        });
+        Scorer { images, templates, stats, mode, color_check }
+    }
+
+    fn out_size(&self) -> (usize, usize) {
+        let (img, template) = (&self.images[0], &self.templates[0]);
+        (img.width() as usize - template.width + 1, img.height() as usize - template.height + 1)
+    }
+
+    /// 位置 (x, y) 的平均颜色是否在容差之内
+    fn color_ok(&self, x: usize, y: usize) -> bool {
+        let Some((tolerance, template_color)) = &self.color_check else {
+            return true;
+        };
+        let means: Vec<f64> = self
+            .stats
+            .iter()
+            .zip(self.templates)
+            .map(|(stats, template)| stats.region(x, y, template).0 as f64 / template.count.max(1) as f64)
+            .collect();
+        let distance = self
+            .mode
+            .decode(&means)
+            .iter()
+            .zip(template_color)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        distance <= *tolerance as f64
+    }
+
+    /// 合并各通道的 NCC
+    fn combine(&self, channel_scores: impl Iterator<Item = f32>) -> f32 {
+        let (mut total, mut used) = (0.0f32, 0);
+        for (score, template) in channel_scores.zip(self.templates) {
+            if template.stddev > 0.0 {
+                total += score;
+                used += 1;
+            }
+        }
+        if used == 0 { 0.0 } else { total / used as f32 }
+    }
+
+    /// 直接计算位置 (x, y) 的得分
+    fn score_at(&self, x: usize, y: usize) -> f32 {
+        if !self.color_ok(x, y) {
+            return 0.0;
+        }
+        self.combine(self.images.iter().zip(self.templates).zip(&self.stats).map(|((img, template), stats)| {
+            ncc_from_correlation(correlate_at(img, template, x, y), stats, template, x, y)
+        }))
+    }
+
+    /// 计算所有位置的得分图，行优先、宽为 `img_width - template_width + 1`
+    fn score_map(&self) -> Vec<f32> {
+        let maps: Vec<Vec<f32>> = self
+            .images
+            .iter()
+            .zip(self.templates)
+            .zip(&self.stats)
+            .map(|((img, template), stats)| score_map(img, template, stats))
+            .collect();
+        let (out_width, out_height) = self.out_size();
+        let mut scores = vec![0.0f32; out_width * out_height];
+        scores.par_chunks_mut(out_width).enumerate().for_each(|(y, row)| {
+            for (x, score) in row.iter_mut().enumerate() {
+                let i = y * out_width + x;
+                *score = if self.color_ok(x, y) { self.combine(maps.iter().map(|map| map[i])) } else { 0.0 };
+            }
+        });
+        scores
+    }
+}
+
+/// 已按颜色空间拆分通道、算好均值与标准差的模板，可以在多次匹配之间复用
+pub struct Template {
+    width: u32,
+    height: u32,
+    color: ColorMode,
+    channels: Vec<GrayImage>,
+    mask: Option<GrayImage>,
+    prepared: Vec<PreparedTemplate>,
+}
+
+impl Template {
+    /// 掩码与模板尺寸不一致或没有参与匹配的像素时返回 None
+    pub fn new(template: &DynamicImage, mask: Option<&GrayImage>, color: ColorMode) -> Option<Self> {
+        if !mask_fits(mask, template) {
+            return None;
+        }
+        let channels = color.split(template);
+        let prepared = channels.iter().map(|t| PreparedTemplate::new(t, mask)).collect();
+        Some(Template { width: template.width(), height: template.height(), color, channels, mask: mask.cloned(), prepared })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+}
+
+/// 金字塔匹配：缩小后的得分图只用于挑选候选，返回的得分是原分辨率下的 NCC
+fn match_template_pyramid(img_channels: &[GrayImage], template: &Template, levels: u32, options: &MatchOptions) -> Option<(u32, u32, f32)> {
+    let factor = 1u32 << levels;
+    let small_img: Vec<GrayImage> = img_channels.iter().map(|img| downscale(img, factor)).collect();
+    let small_template: Vec<GrayImage> = template.channels.iter().map(|t| downscale(t, factor)).collect();
+    let small_mask = template.mask.as_ref().map(|mask| downscale(mask, factor));
+    let (small_template_width, small_template_height) = small_template[0].dimensions();
+    if small_template_width == 0 || small_template_height == 0
+        || small_template_width > small_img[0].width() || small_template_height > small_img[0].height() {
+        return None;
+    }
+
+    let small_prepared: Vec<PreparedTemplate> = small_template.iter().map(|t| PreparedTemplate::new(t, small_mask.as_ref())).collect();
+    let small_scorer = Scorer::new(&small_img, &small_prepared, template.color, options.color_tolerance, false);
+    let small_scores = small_scorer.score_map();
+    let (small_width, small_height) = small_scorer.out_size();
+    let candidates = top_candidates(&small_scores, small_width, small_height, PYRAMID_CANDIDATES);
+
+    let scorer = Scorer::new(img_channels, &template.prepared, template.color, options.color_tolerance, true);
+    let (out_width, out_height) = scorer.out_size();
+    let (max_x, max_y) = (out_width - 1, out_height - 1);
+    let radius = factor as usize;
+    let first_threshold = options.search.first_threshold();
+    let mut best: Option<(u32, u32, f32)> = None;
+    let mut first: Option<(u32, u32, f32)> = None;
+    for (cx, cy) in candidates {
+        let (fx, fy) = (cx * factor as usize, cy * factor as usize);
+        for y in fy.saturating_sub(radius)..=(fy + radius).min(max_y) {
+            for x in fx.saturating_sub(radius)..=(fx + radius).min(max_x) {
+                let ncc = scorer.score_at(x, y);
+                let better = match best {
+                    None => true,
+                    Some((bx, by, b)) => ncc > b || (ncc == b && (y as u32, x as u32) < (by, bx)),
+                };
+                if better {
+                    best = Some((x as u32, y as u32, ncc));
+                }
+                // 候选区域可能重叠、顺序不定，按坐标比较取扫描顺序最靠前的
+                let earlier = first.is_none_or(|(fx, fy, _)| (y as u32, x as u32) < (fy, fx));
+                if first_threshold.is_some_and(|threshold| ncc > threshold) && earlier {
+                    first = Some((x as u32, y as u32, ncc));
+                }
+            }
+        }
+    }
+    first.or(best)
+}
+
+/// 两个同尺寸矩形是否重叠
+fn overlaps(a: (u32, u32), b: (u32, u32), width: u32, height: u32) -> bool {
+    a.0.abs_diff(b.0) < width && a.1.abs_diff(b.1) < height
+}
+
+/// 返回所有得分不低于 threshold 的匹配位置，按得分从高到低排列。
+/// 相互重叠的匹配只保留得分最高的一个（非极大值抑制），`max_results` 为 0 时不限制数量。
+pub fn match_prepared_all(img: &DynamicImage, template: &Template, threshold: f32, max_results: usize, options: &MatchOptions) -> Vec<(u32, u32, f32)> {
+    let (img_width, img_height) = img.dimensions();
+    let (template_width, template_height) = (template.width, template.height);
+    if template_width > img_width || template_height > img_height {
+        println!("模板尺寸不能大于图像尺寸！");
+        return Vec::new();
+    }
+
+    let img_channels = template.color.split(img);
+    let scorer = Scorer::new(&img_channels, &template.prepared, template.color, options.color_tolerance, false);
+    let scores = scorer.score_map();
+    let (out_width, out_height) = scorer.out_size();
+
+    // 先取局部最大值减少候选数量，再做非极大值抑制
+    let peaks: Vec<(u32, u32, f32)> = top_candidates(&scores, out_width, out_height, usize::MAX)
+        .into_iter()
+        .map(|(x, y)| (x as u32, y as u32, scores[y * out_width + x]))
+        .take_while(|&(_, _, score)| score >= threshold)
+        .collect();
+
+    let mut kept: Vec<(u32, u32, f32)> = Vec::new();
+    for peak in peaks {
+        if kept.iter().any(|k| overlaps((k.0, k.1), (peak.0, peak.1), template_width, template_height)) {
+            continue;
+        }
+        kept.push(peak);
+        if max_results > 0 && kept.len() >= max_results {
+            break;
+        }
+    }
+    kept
+}
+
+/// 从模板的 alpha 通道生成掩码，alpha 小于 128 的像素不参与匹配；
+/// 没有 alpha 通道或所有像素都不透明时返回 None
+pub fn alpha_mask(template: &DynamicImage) -> Option<GrayImage> {
+    if !template.color().has_alpha() {
+        return None;
+    }
+    let rgba = template.to_rgba8();
+    if rgba.pixels().all(|p| p[3] >= MASK_LEVEL) {
+        return None;
+    }
+    Some(GrayImage::from_fn(rgba.width(), rgba.height(), |x, y| Luma([rgba.get_pixel(x, y)[3]])))
+}
+
+/// 把单独的掩码图转换为掩码：有 alpha 通道时使用 alpha，否则使用亮度，不小于 128 的像素参与匹配
+pub fn mask_from_image(mask: &DynamicImage) -> GrayImage {
+    alpha_mask(mask).unwrap_or_else(|| mask.to_luma8())
+}
+
+/// 按角度（度，顺时针为正）旋转模板及其掩码。
+/// 模板先放到足够容纳任意旋转的画布中央，旋转后移出原模板的部分在掩码中为 0，
+/// 最后裁剪到掩码的外接矩形，因此旋转后的模板总是带掩码
+pub fn rotate_template(template: &DynamicImage, mask: Option<&GrayImage>, degrees: f32) -> (DynamicImage, GrayImage) {
+    let (width, height) = template.dimensions();
+    let side = ((width * width + height * height) as f32).sqrt().ceil() as u32;
+    let (offset_x, offset_y) = ((side - width) / 2, (side - height) / 2);
+
+    let mut canvas = RgbaImage::new(side, side);
+    image::imageops::replace(&mut canvas, &template.to_rgba8(), offset_x as i64, offset_y as i64);
+    let mut mask_canvas = GrayImage::new(side, side);
+    let full_mask;
+    let mask = match mask {
+        Some(mask) => mask,
+        None => {
+            full_mask = GrayImage::from_pixel(width, height, Luma([255]));
+            &full_mask
+        }
+    };
+    image::imageops::replace(&mut mask_canvas, mask, offset_x as i64, offset_y as i64);
+
+    let theta = degrees.to_radians();
+    let rotated = rotate_about_center(&canvas, theta, Interpolation::Bilinear, Rgba([0, 0, 0, 0]));
+    let rotated_mask = rotate_about_center(&mask_canvas, theta, Interpolation::Bilinear, Luma([0]));
+
+    // 裁剪到掩码中参与匹配的像素的外接矩形
+    let (mut min_x, mut min_y, mut max_x, mut max_y) = (side, side, 0, 0);
+    for (x, y, pixel) in rotated_mask.enumerate_pixels() {
+        if pixel[0] >= MASK_LEVEL {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+    if min_x > max_x || min_y > max_y {
+        return (DynamicImage::ImageRgba8(rotated), rotated_mask);
+    }
+    let (crop_width, crop_height) = (max_x - min_x + 1, max_y - min_y + 1);
+    (
+        DynamicImage::ImageRgba8(rotated.view(min_x, min_y, crop_width, crop_height).to_image()),
+        rotated_mask.view(min_x, min_y, crop_width, crop_height).to_image(),
+    )
+}
+
+/// 检查掩码尺寸与模板一致且至少有一个像素参与匹配
+fn mask_fits(mask: Option<&GrayImage>, template: &DynamicImage) -> bool {
+    let Some(mask) = mask else {
+        return true;
+    };
+    if mask.dimensions() != template.dimensions() {
+        error!("掩码尺寸与模板尺寸不一致，{:?} != {:?}", mask.dimensions(), template.dimensions());
+        return false;
+    }
+    if mask.pixels().all(|p| p[0] < MASK_LEVEL) {
+        error!("掩码中没有参与匹配的像素");
+        return false;
+    }
+    true
+}
+
+/// 模板匹配函数，按 `options.search` 返回全局最佳或扫描顺序中第一个超过阈值的位置
+///
+/// 直接计算时按行并行，`SearchPolicy::First` 下某一行找到超过阈值的位置后，更靠后的行不再计算；
+/// 使用 FFT 时一次算出全部位置。结果与线程数无关。`mask` 中小于 128 的像素不参与计算。
+pub fn match_template(img: &DynamicImage, template: &DynamicImage, mask: Option<&GrayImage>, options: &MatchOptions) -> Option<(u32, u32, f32)> {
+    match_prepared(img, &Template::new(template, mask, options.color)?, options)
+}
+
+/// 同 match_template，使用预先准备好的模板，模板的统计量不再重新计算
+pub fn match_prepared(img: &DynamicImage, template: &Template, options: &MatchOptions) -> Option<(u32, u32, f32)> {
+    // 获取图像尺寸
+    let (img_width, img_height) = img.dimensions();
+    let (template_width, template_height) = (template.width, template.height);
+
+    // 确保模板尺寸不大于图像尺寸
+    if template_width > img_width || template_height > img_height {
+        println!("模板尺寸不能大于图像尺寸！");
+        return None;
+    }
+
+    // 按颜色空间拆分通道，灰度模式只有一个通道
+    let img_channels = template.color.split(img);
+
+    if options.pyramid {
+        let levels = pyramid_levels(&template.channels[0], options);
+        if levels > 0 {
+            if let Some(result) = match_template_pyramid(&img_channels, template, levels, options) {
+                return Some(result);
+            }
+            info!("金字塔匹配失败，回退到原分辨率匹配");
+        }
+    }
+
+    let use_fft = prefer_fft(img_width as usize, img_height as usize, &template.prepared[0]);
+    let scorer = Scorer::new(&img_channels, &template.prepared, template.color, options.color_tolerance, !use_fft);
+    let (out_width, out_height) = scorer.out_size();
+
+    let first_threshold = options.search.first_threshold();
+    if use_fft {
+        let scores = scorer.score_map();
+        let mut best = (0, 0, f32::NEG_INFINITY);
+        for y in 0..out_height {
+            for x in 0..out_width {
+                let ncc = scores[y * out_width + x];
+                if first_threshold.is_some_and(|threshold| ncc > threshold) {
+                    return Some((x as u32, y as u32, ncc));
+                }
+                if ncc > best.2 {
+                    best = (x as u32, y as u32, ncc);
+                }
+            }
+        }
+        return Some(best);
+    }
+
+    // 已知的第一个超过阈值的行；更靠后的行不可能是结果，直接跳过。
+    // 在它之前的行总会被计算，所以结果与调度顺序无关
+    let first_row = AtomicUsize::new(usize::MAX);
+    let rows: Vec<_> = (0..out_height)
+        .into_par_iter()
+        .filter_map(|y| {
+            if y > first_row.load(Ordering::Relaxed) {
+                return None;
+            }
+            let mut row_best = (0, y as u32, f32::NEG_INFINITY);
+            for x in 0..out_width {
+                let ncc = scorer.score_at(x, y);
+                if first_threshold.is_some_and(|threshold| ncc > threshold) {
+                    first_row.fetch_min(y, Ordering::Relaxed);
+                    return Some((row_best, Some((x as u32, y as u32, ncc))));
+                }
+                if ncc > row_best.2 {
+                    row_best = (x as u32, y as u32, ncc);
+                }
+            }
+            Some((row_best, None))
+        })
+        .collect();
+    if let Some(first) = rows.iter().find_map(|(_, first)| *first) {
+        return Some(first);
+    }
+    rows.into_iter()
+        .map(|(row_best, _)| row_best)
+        .reduce(|a, b| if b.2 > a.2 || (b.2 == a.2 && b.1 < a.1) { b } else { a })
+}
+
+/// 计算两个图像区域之间的归一化互相关 (Normalized Cross-Correlation, NCC)
+fn normalized_cross_correlation(
+    img_region: &[u8],
+    template: &[u8],
+    template_size: usize,
+    template_mean: f32,
+    template_stddev: f32,
+) -> f32 {
+
+    // 计算图像区域的均值和标准差
+    let img_mean = img_region.iter().map(|&x| x as u32).sum::<u32>() as f32 / template_size as f32;
+    let img_variance = img_region
+        .iter()
+        .map(|&x| (x as f32 - img_mean).powi(2))
+        .sum::<f32>() / template_size as f32;
+    let img_stddev = img_variance.sqrt();
+
+    // 如果标准差为0，避免除以0
+    if img_stddev == 0.0 || template_stddev == 0.0 {
+        return 0.0;
+    }
+
+    // 计算归一化互相关
+    let mut ncc_sum = 0.0;
+    for i in 0..template_size {
+        ncc_sum += ((img_region[i] as f32 - img_mean) * (template[i] as f32 - template_mean)) / (img_stddev * template_stddev);
+    }
+    ncc_sum / template_size as f32
+}
+
+/// 原来的逐像素模板匹配实现，仅用于性能对比 (--bench-match)
+fn match_template_legacy(img: &DynamicImage, template: &DynamicImage, best_ncc: f32) -> Option<(u32, u32, f32)> {
+    // 将图像转换为灰度图
+    let img_gray = img.to_luma8();
+    let template_gray = template.to_luma8();
+
+    // 获取图像尺寸
+    let (img_width, img_height) = img_gray.dimensions();
+    let (template_width, template_height) = template_gray.dimensions();
+
+    // 确保模板尺寸不大于图像尺寸
+    if template_width > img_width || template_height > img_height {
+        println!("模板尺寸不能大于图像尺寸！");
+        return None;
+    }
+
+    // 将模板图像数据转换为向量
+    let template_data: Vec<u8> = template_gray.into_raw();
+    let template_size = (template_width * template_height) as usize;
+
+    // 计算模板的均值和标准差
+    let template_mean = template_data.iter().map(|&x| x as u32).sum::<u32>() as f32 / template_size as f32;
+    let template_variance = template_data
+        .iter()
+        .map(|&x| (x as f32 - template_mean).powi(2))
+        .sum::<f32>() / template_size as f32;
+    let template_stddev = template_variance.sqrt();
+
+    // 使用 Arc 和 Mutex 来共享和同步最大相关系数和最佳匹配位置
+    let max_ncc = Arc::new(Mutex::new(f32::NEG_INFINITY));
+    let best_match_pos = Arc::new(Mutex::new((0, 0, 0.0)));
+
+    // 提前退出标志
+    let stop_flag = Arc::new(AtomicBool::new(false));
+
+    // 使用 rayon 的 par_iter 并行化外层的 y 循环
+    (0..(img_height - template_height + 1)).into_par_iter().for_each(|y| {
+        if stop_flag.load(Ordering::Relaxed) {
+            return;
+        }
+        for x in 0..(img_width - template_width + 1) {
+            // 使用 sub_image 创建一个子图像视图
+            let img_sub_image = img_gray.view(x, y, template_width, template_height);
+
+            // 将子图像视图转换为新的 ImageBuffer
+            let img_region: ImageBuffer<Luma<u8>, Vec<u8>> = img_sub_image.to_image();
+
+            // 提取当前窗口的图像区域数据
+            let img_region_data: Vec<u8> = img_region.into_raw();
+
+            // 计算归一化互相关
+            let ncc = normalized_cross_correlation(&img_region_data, &template_data, template_size, template_mean, template_stddev);
+
+            // 更新最大相关系数和最佳匹配位置
+            let mut max_ncc_lock = max_ncc.lock().unwrap();
+            let mut best_match_pos_lock = best_match_pos.lock().unwrap();
+            if ncc > *max_ncc_lock {
+                *max_ncc_lock = ncc;
+                *best_match_pos_lock = (x, y, ncc);
+                // 如果找到非常高的 NCC 值，设置提前退出标志
+                if ncc > best_ncc {
+                    stop_flag.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+
+    // 返回最佳匹配位置
+    let result = Some(*best_match_pos.lock().unwrap()); result
+}
+
+/// 对比新旧两种实现的耗时与结果：`action --bench-match <截图> <模板> [轮数]`
+pub fn bench(image_file: &str, template_file: &str, rounds: usize) -> Result<(), String> {
+    let img = image::open(image_file).map_err(|e| format!("打开截图失败，{:?}: {:?}", image_file, e))?;
+    let template = image::open(template_file).map_err(|e| format!("打开模版文件失败，{:?}: {:?}", template_file, e))?;
+    info!("截图尺寸: ({}, {}), 模版尺寸: ({}, {}), 轮数: {}", img.width(), img.height(), template.width(), template.height(), rounds);
+
+    let mask = alpha_mask(&template);
+    let default_options = MatchOptions::default();
+    let pyramid_options = MatchOptions { pyramid: true, ..MatchOptions::default() };
+    let implementations: [(&str, &dyn Fn() -> Option<(u32, u32, f32)>); 3] = [
+        // 阈值大于 1 时不会提前退出，各实现都扫描全部位置
+        ("legacy", &|| match_template_legacy(&img, &template, 1.1)),
+        ("integral+fft", &|| match_template(&img, &template, mask.as_ref(), &default_options)),
+        ("pyramid", &|| match_template(&img, &template, mask.as_ref(), &pyramid_options)),
+    ];
+    for (name, implementation) in implementations {
+        let mut result = None;
+        let start = Instant::now();
+        for _ in 0..rounds {
+            result = implementation();
+        }
+        info!("[{}] 结果: {:?}, 平均耗时: {:?}", name, result, start.elapsed() / rounds.max(1) as u32);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    /// 固定种子的伪随机灰度图
+    fn noise(width: u32, height: u32, seed: u64) -> GrayImage {
+        let mut state = seed;
+        GrayImage::from_fn(width, height, |_, _| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            Luma([(state >> 56) as u8])
+        })
+    }
+
+    /// 截图中 (50, 80) 处是模板本身，扫描顺序更靠前的 (10, 20) 处是加了轻微噪声的模板
+    fn scene(template_size: u32) -> (DynamicImage, DynamicImage) {
+        let mut img = noise(160, 160, 1);
+        let template = noise(template_size, template_size, 2);
+        let perturbation = noise(template_size, template_size, 3);
+        for (x, y, pixel) in template.enumerate_pixels() {
+            img.put_pixel(50 + x, 80 + y, *pixel);
+            let offset = perturbation.get_pixel(x, y)[0] as i32 / 16 - 8;
+            img.put_pixel(10 + x, 20 + y, Luma([(pixel[0] as i32 + offset).clamp(0, 255) as u8]));
+        }
+        (DynamicImage::ImageLuma8(img), DynamicImage::ImageLuma8(template))
+    }
+
+    /// 在不同线程数的线程池中匹配，结果必须完全相同
+    fn match_across_threads(img: &DynamicImage, template: &DynamicImage, mask: Option<&GrayImage>, options: &MatchOptions) -> Option<(u32, u32, f32)> {
+        let results: Vec<Option<(u32, u32, f32)>> = [1, 2, 3, 8]
+            .iter()
+            .map(|&threads| {
+                let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+                pool.install(|| match_template(img, template, mask, options))
+            })
+            .collect();
+        for result in &results[1..] {
+            assert_eq!(*result, results[0]);
+        }
+        results[0]
+    }
+
+    fn uses_fft(img: &DynamicImage, template: &DynamicImage) -> bool {
+        let prepared = PreparedTemplate::new(&template.to_luma8(), None);
+        prefer_fft(img.width() as usize, img.height() as usize, &prepared)
+    }
+
+    fn first(threshold: f32) -> MatchOptions {
+        MatchOptions { search: SearchPolicy::First(threshold), ..MatchOptions::default() }
+    }
+
+    /// 圆形掩码
+    fn disc_mask(size: u32) -> GrayImage {
+        let r = size as i32 / 2;
+        GrayImage::from_fn(size, size, |x, y| Luma([if (x as i32 - r).pow(2) + (y as i32 - r).pow(2) <= (r - 1).pow(2) { 255 } else { 0 }]))
+    }
+
+    /// 用原来的逐像素 NCC 计算每个位置的得分，有掩码时只取掩码内的像素
+    fn legacy_score_map(img: &GrayImage, template: &GrayImage, mask: Option<&GrayImage>) -> Vec<f32> {
+        let (width, height) = template.dimensions();
+        let included = |x: u32, y: u32| mask.is_none_or(|mask| mask.get_pixel(x, y)[0] >= MASK_LEVEL);
+        let gather = |img: &GrayImage, left: u32, top: u32| -> Vec<u8> {
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .filter(|&(x, y)| included(x, y))
+                .map(|(x, y)| img.get_pixel(left + x, top + y)[0])
+                .collect()
+        };
+        let template_data = gather(template, 0, 0);
+        let size = template_data.len();
+        let mean = template_data.iter().map(|&v| v as f32).sum::<f32>() / size as f32;
+        let stddev = (template_data.iter().map(|&v| (v as f32 - mean).powi(2)).sum::<f32>() / size as f32).sqrt();
+        let mut scores = Vec::new();
+        for y in 0..=img.height() - height {
+            for x in 0..=img.width() - width {
+                scores.push(normalized_cross_correlation(&gather(img, x, y), &template_data, size, mean, stddev));
+            }
+        }
+        scores
+    }
+
+    /// 新实现的得分图与原来的实现逐位置比较
+    fn assert_matches_legacy(template_size: u32, fft: bool, masked: bool) {
+        let (img, template) = scene(template_size);
+        let (img, template) = (img.to_luma8(), template.to_luma8());
+        let mask = masked.then(|| disc_mask(template_size));
+        let prepared = PreparedTemplate::new(&template, mask.as_ref());
+        assert_eq!(prefer_fft(img.width() as usize, img.height() as usize, &prepared), fft);
+        let scores = score_map(&img, &prepared, &ImageStats::new(&img, &prepared));
+        let expected = legacy_score_map(&img, &template, mask.as_ref());
+        assert_eq!(scores.len(), expected.len());
+        for (i, (score, expected)) in scores.iter().zip(&expected).enumerate() {
+            assert!((score - expected).abs() < 1e-3, "位置 {}: {} != {}", i, score, expected);
+        }
+    }
+
+    #[test]
+    fn direct_scores_match_legacy() {
+        assert_matches_legacy(10, false, false);
+    }
+
+    #[test]
+    fn fft_scores_match_legacy() {
+        assert_matches_legacy(48, true, false);
+    }
+
+    #[test]
+    fn masked_scores_match_legacy() {
+        assert_matches_legacy(10, false, true);
+        assert_matches_legacy(48, true, true);
+    }
+
+    #[test]
+    fn best_match_agrees_with_legacy() {
+        for size in [10, 48] {
+            let (img, template) = scene(size);
+            let (x, y, score) = match_template(&img, &template, None, &MatchOptions::default()).unwrap();
+            let (legacy_x, legacy_y, legacy_score) = match_template_legacy(&img, &template, 1.1).unwrap();
+            assert_eq!((x, y), (legacy_x, legacy_y));
+            assert!((score - legacy_score).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn direct_best_is_global_best() {
+        let (img, template) = scene(10);
+        assert!(!uses_fft(&img, &template));
+        let (x, y, score) = match_across_threads(&img, &template, None, &MatchOptions::default()).unwrap();
+        assert_eq!((x, y), (50, 80));
+        assert!(score > 0.999);
+    }
+
+    #[test]
+    fn direct_first_is_first_in_scan_order() {
+        let (img, template) = scene(10);
+        let (x, y, score) = match_across_threads(&img, &template, None, &first(0.9)).unwrap();
+        assert_eq!((x, y), (10, 20));
+        assert!(score > 0.9 && score < 0.999);
+    }
+
+    #[test]
+    fn fft_best_and_first() {
+        let (img, template) = scene(48);
+        assert!(uses_fft(&img, &template));
+        let best = match_across_threads(&img, &template, None, &MatchOptions::default()).unwrap();
+        assert_eq!((best.0, best.1), (50, 80));
+        let first = match_across_threads(&img, &template, None, &first(0.9)).unwrap();
+        assert_eq!((first.0, first.1), (10, 20));
+    }
+
+    #[test]
+    fn first_without_match_falls_back_to_best() {
+        for size in [10, 48] {
+            let (img, template) = scene(size);
+            let best = match_across_threads(&img, &template, None, &MatchOptions::default());
+            assert_eq!(match_across_threads(&img, &template, None, &first(1.5)), best);
+        }
+    }
+
+    #[test]
+    fn all_matches_suppress_overlaps_and_sort_by_score() {
+        // (10, 20) 与 (64, 92) 是加了轻微噪声的模板，(50, 80) 处的模板本身最后放入，盖住 (64, 92) 的左上角
+        let mut img = noise(160, 160, 1);
+        let template = noise(24, 24, 2);
+        let perturbation = noise(24, 24, 3);
+        for (left, top, exact) in [(10, 20, false), (64, 92, false), (50, 80, true)] {
+            for (x, y, pixel) in template.enumerate_pixels() {
+                let offset = if exact { 0 } else { perturbation.get_pixel(x, y)[0] as i32 / 16 - 8 };
+                img.put_pixel(left + x, top + y, Luma([(pixel[0] as i32 + offset).clamp(0, 255) as u8]));
+            }
+        }
+        let threshold = 0.5;
+        let overlapped = legacy_score_map(&img, &template, None)[92 * (160 - 23) + 64];
+        assert!(overlapped >= threshold, "{}", overlapped);
+
+        let img = DynamicImage::ImageLuma8(img);
+        let template = Template::new(&DynamicImage::ImageLuma8(template), None, ColorMode::Gray).unwrap();
+        let options = MatchOptions::default();
+        let all = match_prepared_all(&img, &template, threshold, 0, &options);
+        let positions: Vec<(u32, u32)> = all.iter().map(|&(x, y, _)| (x, y)).collect();
+        assert_eq!(positions, [(50, 80), (10, 20)]);
+        assert!(all[0].2 > 0.999 && all[1].2 < all[0].2, "{:?}", all);
+        assert!(all.windows(2).all(|pair| pair[0].2 >= pair[1].2));
+
+        let limited = match_prepared_all(&img, &template, threshold, 1, &options);
+        assert_eq!(limited, all[..1]);
+        let strict = match_prepared_all(&img, &template, (all[0].2 + all[1].2) / 2.0, 0, &options);
+        assert_eq!(strict, all[..1]);
+    }
+
+    #[test]
+    fn pyramid_is_deterministic() {
+        let (img, template) = scene(32);
+        let options = MatchOptions { pyramid: true, ..MatchOptions::default() };
+        let (x, y, _) = match_across_threads(&img, &template, None, &options).unwrap();
+        assert_eq!((x, y), (50, 80));
+        let options = MatchOptions { search: SearchPolicy::First(0.9), ..options };
+        match_across_threads(&img, &template, None, &options).unwrap();
+    }
+
+    #[test]
+    fn pyramid_levels_are_clamped() {
+        let (img, template) = scene(32);
+        let options = MatchOptions { pyramid: true, pyramid_levels: 32, ..MatchOptions::default() };
+        assert_eq!(pyramid_levels(&template.to_luma8(), &options), PYRAMID_MAX_LEVELS);
+        // 超出上限的层数与上限相同，不会溢出
+        let clamped = match_across_threads(&img, &template, None, &options);
+        let options = MatchOptions { pyramid_levels: PYRAMID_MAX_LEVELS, ..options };
+        assert_eq!(match_across_threads(&img, &template, None, &options), clamped);
+        let options = MatchOptions { pyramid_levels: 2, ..options };
+        assert_eq!(pyramid_levels(&template.to_luma8(), &options), 2);
+        assert_eq!(match_across_threads(&img, &template, None, &options).map(|(x, y, _)| (x, y)), Some((50, 80)));
+    }
+
+    #[test]
+    fn masked_score_ignores_masked_out_pixels() {
+        let (img, template) = scene(16);
+        let mask = disc_mask(16);
+        // 把 (50, 80) 处掩码外的像素换成无关的噪声
+        let mut img = img.to_luma8();
+        let replacement = noise(16, 16, 9);
+        for (x, y, pixel) in mask.enumerate_pixels() {
+            if pixel[0] < MASK_LEVEL {
+                img.put_pixel(50 + x, 80 + y, *replacement.get_pixel(x, y));
+            }
+        }
+        let img = DynamicImage::ImageLuma8(img);
+        let (x, y, score) = match_across_threads(&img, &template, Some(&mask), &MatchOptions::default()).unwrap();
+        assert_eq!((x, y), (50, 80));
+        assert!(score > 0.999, "{}", score);
+        let prepared = PreparedTemplate::new(&template.to_luma8(), None);
+        let gray = img.to_luma8();
+        let unmasked = score_map(&gray, &prepared, &ImageStats::new(&gray, &prepared));
+        let unmasked_score = unmasked[80 * (gray.width() as usize - 15) + 50];
+        assert!(unmasked_score < 0.9, "{}", unmasked_score);
+    }
+
+    /// 金色按钮与逐像素亮度相同的灰色按钮，灰色的在扫描顺序中更靠前
+    fn gold_and_grey_buttons() -> (DynamicImage, DynamicImage) {
+        let channel = |seed| noise(20, 12, seed);
+        let (r, g, b) = (channel(11), channel(12), channel(13));
+        let gold = RgbImage::from_fn(20, 12, |x, y| {
+            let texture = |c: &GrayImage, base: u8| base + c.get_pixel(x, y)[0] / 4;
+            Rgb([texture(&r, 180), texture(&g, 140), texture(&b, 20)])
+        });
+        let grey = DynamicImage::ImageRgb8(gold.clone()).to_luma8();
+        let background = noise(160, 120, 14);
+        let mut img = RgbImage::from_fn(160, 120, |x, y| {
+            let v = background.get_pixel(x, y)[0];
+            Rgb([v, v / 2 + 64, 255 - v])
+        });
+        for (x, y, pixel) in gold.enumerate_pixels() {
+            let v = grey.get_pixel(x, y)[0];
+            img.put_pixel(10 + x, 10 + y, Rgb([v, v, v]));
+            img.put_pixel(100 + x, 60 + y, *pixel);
+        }
+        (DynamicImage::ImageRgb8(img), DynamicImage::ImageRgb8(gold))
+    }
+
+    #[test]
+    fn color_modes_separate_grey_from_gold() {
+        let (img, gold) = gold_and_grey_buttons();
+        // 灰度下两个按钮完全相同，颜色容差也无法区分，取扫描顺序靠前的灰色按钮
+        for color_tolerance in [None, Some(30.0)] {
+            let options = MatchOptions { color_tolerance, ..MatchOptions::default() };
+            let (x, y, score) = match_template(&img, &gold, None, &options).unwrap();
+            assert_eq!((x, y), (10, 10));
+            assert!(score > 0.999, "{}", score);
+        }
+        for color in [ColorMode::Rgb, ColorMode::Lab] {
+            let options = MatchOptions { color, ..MatchOptions::default() };
+            let (x, y, score) = match_template(&img, &gold, None, &options).unwrap();
+            assert_eq!((x, y), (100, 60), "{:?}", color);
+            assert!(score > 0.999, "{:?} {}", color, score);
+            // 即使只要求达到阈值就返回，灰色按钮也达不到
+            let options = MatchOptions { search: SearchPolicy::First(0.9), ..options };
+            assert_eq!(match_template(&img, &gold, None, &options).map(|(x, y, _)| (x, y)), Some((100, 60)), "{:?}", color);
+        }
+    }
+
+    #[test]
+    fn rgb_color_tolerance_checks_each_channel() {
+        let (_, gold) = gold_and_grey_buttons();
+        let gold_rgb = gold.to_rgb8();
+        let background = noise(160, 120, 14);
+        // 只有 R 通道整体偏移 shift，逐通道的 NCC 不变，只有颜色容差能区分
+        let scene = |shift: u8| {
+            let mut img = RgbImage::from_fn(160, 120, |x, y| {
+                let v = background.get_pixel(x, y)[0];
+                Rgb([v, v / 2 + 64, 255 - v])
+            });
+            for (x, y, pixel) in gold_rgb.enumerate_pixels() {
+                img.put_pixel(100 + x, 60 + y, Rgb([pixel[0] - shift, pixel[1], pixel[2]]));
+            }
+            DynamicImage::ImageRgb8(img)
+        };
+        let options = MatchOptions { color: ColorMode::Rgb, color_tolerance: Some(30.0), ..MatchOptions::default() };
+        let (x, y, score) = match_template(&scene(20), &gold, None, &options).unwrap();
+        assert_eq!((x, y), (100, 60));
+        assert!(score > 0.999, "{}", score);
+        let found = match_template(&scene(50), &gold, None, &options);
+        assert!(found.is_none_or(|(x, y, score)| (x, y) != (100, 60) && score < 0.5), "{:?}", found);
+        // 不检查颜色时偏移 50 的按钮照样匹配
+        let options = MatchOptions { color_tolerance: None, ..options };
+        assert_eq!(match_template(&scene(50), &gold, None, &options).map(|(x, y, _)| (x, y)), Some((100, 60)));
+    }
+
+    #[test]
+    fn masked_color_is_deterministic() {
+        let (img, template) = scene(16);
+        let mask = GrayImage::from_fn(16, 16, |x, y| Luma([if (x as i32 - 8).pow(2) + (y as i32 - 8).pow(2) <= 49 { 255 } else { 0 }]));
+        for color in [ColorMode::Gray, ColorMode::Rgb, ColorMode::Lab] {
+            let options = MatchOptions { color, ..MatchOptions::default() };
+            let (x, y, _) = match_across_threads(&img, &template, Some(&mask), &options).unwrap();
+            assert_eq!((x, y), (50, 80));
+            let options = MatchOptions { search: SearchPolicy::First(0.9), ..options };
+            let (x, y, _) = match_across_threads(&img, &template, Some(&mask), &options).unwrap();
+            assert_eq!((x, y), (10, 20));
+        }
+    }
+}
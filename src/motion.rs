@@ -0,0 +1,251 @@
+//! 拟人化的鼠标移动与点击节奏
+//!
+//! 移动轨迹可以是直线、三次 Bezier 曲线或最小加加速度（minimum jerk）曲线，时长按距离和速度计算，
+//! 可以先越过目标再折回。点击位置可以在匹配矩形内随机偏移，点击前的等待和按下时长也可以随机。
+//! 随机数可以设定种子，同一个种子得到完全相同的轨迹和节奏，便于回放测试。
+
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// 轨迹上相邻两点之间的时间间隔
+pub const STEP_MS: u64 = 10;
+/// 移动的最短时长，距离很短时也不会瞬间到达
+const MIN_DURATION_MS: f64 = 60.0;
+/// 距离小于该值时不越过目标
+const MIN_OVERSHOOT_DISTANCE: f64 = 20.0;
+/// Bezier 控制点偏离直线的最大比例（相对移动距离）
+const BEZIER_SPREAD: f64 = 0.25;
+/// 越过目标后折回所用时长占整体的比例
+const CORRECTION_SHARE: f64 = 0.3;
+
+/// 鼠标移动轨迹
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Curve {
+    /// 直接跳到目标位置
+    None,
+    /// 匀速直线
+    Linear,
+    /// 三次 Bezier 曲线，控制点随机偏离直线，速度按最小加加速度曲线变化
+    Bezier,
+    /// 直线，速度按最小加加速度曲线变化（先加速后减速）
+    MinJerk,
+}
+
+impl Curve {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "none" => Some(Curve::None),
+            "linear" => Some(Curve::Linear),
+            "bezier" => Some(Curve::Bezier),
+            "minjerk" | "min-jerk" => Some(Curve::MinJerk),
+            _ => None,
+        }
+    }
+}
+
+/// 鼠标移动与点击选项
+#[derive(Debug, Clone)]
+pub struct MotionOptions {
+    pub curve: Curve,
+    /// 移动速度，像素 / 秒（屏幕逻辑坐标）
+    pub speed: f64,
+    /// 越过目标的最大距离，占移动距离的比例，0 表示不越过
+    pub overshoot: f64,
+    /// 点击位置在匹配矩形内的随机偏移，占矩形半宽、半高的比例，0 表示点击中心
+    pub jitter: f64,
+    /// 移动到位后到按下之前的等待，毫秒，在 [min, max] 内随机
+    pub click_delay: (u64, u64),
+    /// 按下到松开的时长，毫秒，在 [min, max] 内随机，0 表示立即松开
+    pub press_duration: (u64, u64),
+}
+
+impl Default for MotionOptions {
+    fn default() -> Self {
+        MotionOptions {
+            curve: Curve::None,
+            speed: 1500.0,
+            overshoot: 0.0,
+            jitter: 0.0,
+            click_delay: (500, 500),
+            press_duration: (0, 0),
+        }
+    }
+}
+
+static RNG: Lazy<Mutex<StdRng>> = Lazy::new(|| Mutex::new(StdRng::from_entropy()));
+
+/// 设定随机数种子，之后的轨迹、偏移和时长都由种子决定
+pub fn reseed(seed: u64) {
+    *RNG.lock().unwrap() = StdRng::seed_from_u64(seed);
+}
+
+/// 不设种子，恢复为每次运行都不同的随机数
+pub fn reseed_from_entropy() {
+    *RNG.lock().unwrap() = StdRng::from_entropy();
+}
+
+/// [min, max] 内的随机毫秒数
+pub fn random_ms(range: (u64, u64)) -> u64 {
+    let (min, max) = range;
+    if max <= min {
+        return min;
+    }
+    RNG.lock().unwrap().gen_range(min..=max)
+}
+
+/// 矩形 (x, y, width, height) 内的点击位置，jitter 为 0 时是中心
+pub fn click_point(rect: (u32, u32, u32, u32), jitter: f64) -> (u32, u32) {
+    click_point_with(&mut *RNG.lock().unwrap(), rect, jitter)
+}
+
+fn click_point_with(rng: &mut impl Rng, rect: (u32, u32, u32, u32), jitter: f64) -> (u32, u32) {
+    let (x, y, width, height) = rect;
+    let center = (x + width / 2, y + height / 2);
+    // clamp 会原样返回 NaN，gen_range 收到 NaN 范围会 panic，所以 NaN 也按 0 处理
+    if jitter.is_nan() || jitter <= 0.0 || width == 0 || height == 0 {
+        return center;
+    }
+    let jitter = jitter.min(1.0);
+    let half = (width as f64 / 2.0 * jitter, height as f64 / 2.0 * jitter);
+    let px = (center.0 as f64 + rng.gen_range(-half.0..=half.0)).round() as u32;
+    let py = (center.1 as f64 + rng.gen_range(-half.1..=half.1)).round() as u32;
+    (px.clamp(x, x + width - 1), py.clamp(y, y + height - 1))
+}
+
+/// 从 from 移动到 to 的轨迹，每两点间隔 STEP_MS，最后一点一定是 to。
+/// duration 为 None 时按距离和速度计算时长；指定时长时 Curve::None 按匀速直线处理
+pub fn path(from: (i32, i32), to: (i32, i32), options: &MotionOptions, duration: Option<f64>) -> Vec<(i32, i32)> {
+    path_with(&mut *RNG.lock().unwrap(), from, to, options, duration)
+}
+
+fn path_with(rng: &mut impl Rng, from: (i32, i32), to: (i32, i32), options: &MotionOptions, duration: Option<f64>) -> Vec<(i32, i32)> {
+    let curve = match (options.curve, duration) {
+        (Curve::None, None) => return vec![to],
+        (Curve::None, Some(_)) => Curve::Linear,
+        (curve, _) => curve,
+    };
+    let start = (from.0 as f64, from.1 as f64);
+    let end = (to.0 as f64, to.1 as f64);
+    let distance = (end.0 - start.0).hypot(end.1 - start.1);
+    if distance < 1.0 && duration.is_none() {
+        return vec![to];
+    }
+    let duration = duration.unwrap_or_else(|| distance / options.speed.max(1.0) * 1000.0).max(MIN_DURATION_MS);
+
+    let mut points = Vec::new();
+    if options.overshoot > 0.0 && distance >= MIN_OVERSHOOT_DISTANCE {
+        let beyond = distance * options.overshoot * rng.gen_range(0.5..=1.0);
+        let direction = ((end.0 - start.0) / distance, (end.1 - start.1) / distance);
+        let past = (end.0 + direction.0 * beyond, end.1 + direction.1 * beyond);
+        segment(rng, &mut points, start, past, curve, duration * (1.0 - CORRECTION_SHARE));
+        segment(rng, &mut points, past, end, Curve::MinJerk, duration * CORRECTION_SHARE);
+    } else {
+        segment(rng, &mut points, start, end, curve, duration);
+    }
+    if let Some(last) = points.last_mut() {
+        *last = to;
+    }
+    points
+}
+
+/// 追加一段轨迹（不含起点）
+fn segment(rng: &mut impl Rng, points: &mut Vec<(i32, i32)>, start: (f64, f64), end: (f64, f64), curve: Curve, duration: f64) {
+    let steps = ((duration / STEP_MS as f64).ceil() as usize).max(1);
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    // 控制点沿直线方向放在 1/3、2/3 处，再沿法线方向随机偏移
+    let mut spread = || rng.gen_range(-BEZIER_SPREAD..=BEZIER_SPREAD);
+    let (s1, s2) = (spread(), spread());
+    let c1 = (start.0 + dx / 3.0 - dy * s1, start.1 + dy / 3.0 + dx * s1);
+    let c2 = (start.0 + dx * 2.0 / 3.0 - dy * s2, start.1 + dy * 2.0 / 3.0 + dx * s2);
+    for step in 1..=steps {
+        let t = step as f64 / steps as f64;
+        let (x, y) = match curve {
+            Curve::Bezier => bezier(start, c1, c2, end, min_jerk(t)),
+            Curve::MinJerk => {
+                let s = min_jerk(t);
+                (start.0 + dx * s, start.1 + dy * s)
+            }
+            _ => (start.0 + dx * t, start.1 + dy * t),
+        };
+        points.push((x.round() as i32, y.round() as i32));
+    }
+}
+
+/// 最小加加速度曲线的位置比例：起止速度与加速度都为 0
+fn min_jerk(t: f64) -> f64 {
+    t * t * t * (10.0 - 15.0 * t + 6.0 * t * t)
+}
+
+fn bezier(p0: (f64, f64), p1: (f64, f64), p2: (f64, f64), p3: (f64, f64), t: f64) -> (f64, f64) {
+    let u = 1.0 - t;
+    let (a, b, c, d) = (u * u * u, 3.0 * u * u * t, 3.0 * u * t * t, t * t * t);
+    (
+        a * p0.0 + b * p1.0 + c * p2.0 + d * p3.0,
+        a * p0.1 + b * p1.1 + c * p2.1 + d * p3.1,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(curve: Curve) -> MotionOptions {
+        MotionOptions { curve, ..MotionOptions::default() }
+    }
+
+    #[test]
+    fn same_seed_gives_same_path() {
+        let options = MotionOptions { overshoot: 0.2, ..options(Curve::Bezier) };
+        let a = path_with(&mut StdRng::seed_from_u64(7), (10, 20), (600, 380), &options, None);
+        let b = path_with(&mut StdRng::seed_from_u64(7), (10, 20), (600, 380), &options, None);
+        let c = path_with(&mut StdRng::seed_from_u64(8), (10, 20), (600, 380), &options, None);
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn paths_end_on_target_and_follow_speed() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for curve in [Curve::Linear, Curve::Bezier, Curve::MinJerk] {
+            let points = path_with(&mut rng, (0, 0), (300, 400), &options(curve), None);
+            assert_eq!(points.last(), Some(&(300, 400)));
+            // 500 像素，1500 像素 / 秒，约 333 毫秒
+            assert_eq!(points.len(), 34, "{:?}", curve);
+        }
+        assert_eq!(path_with(&mut rng, (0, 0), (300, 400), &options(Curve::None), None), vec![(300, 400)]);
+        assert_eq!(path_with(&mut rng, (0, 0), (300, 400), &options(Curve::None), Some(200.0)).len(), 20);
+    }
+
+    #[test]
+    fn min_jerk_starts_and_ends_slowly() {
+        let points = path_with(&mut StdRng::seed_from_u64(1), (0, 0), (1000, 0), &options(Curve::MinJerk), Some(1000.0));
+        let first = points[0].0;
+        let middle = points[50].0 - points[49].0;
+        let last = 1000 - points[points.len() - 2].0;
+        assert!(first < middle && last < middle, "{} {} {}", first, middle, last);
+    }
+
+    #[test]
+    fn overshoot_passes_the_target() {
+        let options = MotionOptions { overshoot: 0.2, ..options(Curve::MinJerk) };
+        let points = path_with(&mut StdRng::seed_from_u64(3), (0, 0), (500, 0), &options, None);
+        let furthest = points.iter().map(|p| p.0).max().unwrap();
+        assert!((550..=600).contains(&furthest), "{}", furthest);
+        assert_eq!(points.last(), Some(&(500, 0)));
+    }
+
+    #[test]
+    fn jitter_stays_inside_rect() {
+        let mut rng = StdRng::seed_from_u64(5);
+        assert_eq!(click_point_with(&mut rng, (100, 50, 40, 20), 0.0), (120, 60));
+        assert_eq!(click_point_with(&mut rng, (100, 50, 40, 20), f64::NAN), (120, 60));
+        let points: Vec<(u32, u32)> = (0..500).map(|_| click_point_with(&mut rng, (100, 50, 40, 20), 1.0)).collect();
+        assert!(points.iter().all(|&(x, y)| (100..140).contains(&x) && (50..70).contains(&y)));
+        assert!(points.iter().any(|&(x, _)| x < 105) && points.iter().any(|&(x, _)| x > 134));
+        let half: Vec<(u32, u32)> = (0..500).map(|_| click_point_with(&mut rng, (100, 50, 40, 20), 0.5)).collect();
+        assert!(half.iter().all(|&(x, y)| (110..=130).contains(&x) && (55..=65).contains(&y)));
+    }
+}
@@ -0,0 +1,55 @@
+//! macOS 后端：Cocoa 负责激活与缩放系数，xcap 负责窗口枚举与截图
+
+use cocoa::base::{id, nil};
+use image::RgbaImage;
+use objc::runtime::{Class, Object};
+use objc::{msg_send, sel, sel_impl};
+
+use super::{xcap_capture, xcap_windows, Platform, WindowInfo};
+
+pub struct MacOsPlatform;
+
+fn get_backing_scale_factor() -> f32 {
+    unsafe {
+        // 获取 NSScreen 类
+        let ns_screen_class: *const Class = Class::get("NSScreen").expect("Failed to get NSScreen class");
+
+        // 获取主屏幕对象
+        let main_screen: *mut Object = msg_send![ns_screen_class, mainScreen];
+        if main_screen.is_null() {
+            panic!("Failed to get main screen");
+        }
+
+        // 获取 backingScaleFactor
+        let scale_factor: f64 = msg_send![main_screen, backingScaleFactor];
+        scale_factor as f32
+    }
+}
+
+fn active_window(pid: u32) -> bool {
+    let app = Class::get("NSRunningApplication").unwrap();
+    let running_app: id  = unsafe { msg_send![app, runningApplicationWithProcessIdentifier: pid] };
+    if running_app != nil {
+        let _: () = unsafe { msg_send![running_app, activateWithOptions: 0] };
+        return true;
+    }
+    false
+}
+
+impl Platform for MacOsPlatform {
+    fn windows(&self) -> Vec<WindowInfo> {
+        xcap_windows()
+    }
+
+    fn capture(&self, window: &WindowInfo) -> Option<RgbaImage> {
+        xcap_capture(window)
+    }
+
+    fn activate(&self, window: &WindowInfo) -> bool {
+        active_window(window.pid)
+    }
+
+    fn scale_factor(&self) -> f32 {
+        get_backing_scale_factor()
+    }
+}
@@ -0,0 +1,188 @@
+//! 平台抽象层
+//!
+//! 把窗口枚举、截图、激活、屏幕缩放系数和输入注入统一到 [`Platform`] trait 之后，
+//! 脚本层只依赖这个 trait，同一份脚本即可在 macOS 与 Linux(X11) 上运行。
+
+#[cfg(target_os = "macos")]
+mod macos;
+mod replay;
+#[cfg(target_os = "linux")]
+mod x11;
+
+use device_query::{DeviceQuery, DeviceState, Keycode};
+use enigo::{Axis, Button, Coordinate::Abs, Direction, Enigo, Key, Keyboard, Mouse, Settings};
+use image::RgbaImage;
+use log::error;
+use once_cell::sync::OnceCell;
+
+pub use replay::ReplayPlatform;
+
+/// 窗口快照信息，坐标与尺寸均为平台逻辑坐标
+#[derive(Debug, Clone)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub pid: u32,
+    pub app_name: String,
+    pub title: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// 层叠顺序，越大越靠前
+    pub z: i32,
+    pub is_minimized: bool,
+}
+
+impl WindowInfo {
+    /// 从 xcap 的窗口对象生成快照
+    pub fn from_xcap(window: &xcap::Window) -> Self {
+        WindowInfo {
+            id: window.id(),
+            pid: window.pid(),
+            app_name: window.app_name().to_string(),
+            title: window.title().to_string(),
+            x: window.x(),
+            y: window.y(),
+            width: window.width(),
+            height: window.height(),
+            z: window.z(),
+            is_minimized: window.is_minimized(),
+        }
+    }
+}
+
+/// 平台后端需要提供的能力
+pub trait Platform: Send + Sync {
+    /// 枚举当前所有窗口
+    fn windows(&self) -> Vec<WindowInfo>;
+
+    /// 截取窗口图像，返回的图像为物理像素
+    fn capture(&self, window: &WindowInfo) -> Option<RgbaImage>;
+
+    /// 激活（前置）窗口
+    fn activate(&self, window: &WindowInfo) -> bool;
+
+    /// 屏幕缩放系数：截图像素 / 窗口逻辑坐标
+    fn scale_factor(&self) -> f32;
+
+    /// 将鼠标移动到屏幕绝对坐标（逻辑坐标）
+    fn move_mouse(&self, x: i32, y: i32) -> bool {
+        match Enigo::new(&Settings::default()) {
+            Ok(mut enigo) => enigo.move_mouse(x, y, Abs).is_ok(),
+            Err(e) => {
+                error!("初始化输入设备失败: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// 鼠标当前的屏幕坐标（逻辑坐标），取不到时返回 None
+    fn mouse_location(&self) -> Option<(i32, i32)> {
+        match Enigo::new(&Settings::default()) {
+            Ok(enigo) => enigo.location().ok(),
+            Err(e) => {
+                error!("初始化输入设备失败: {:?}", e);
+                None
+            }
+        }
+    }
+
+    /// 发送鼠标按键事件
+    fn mouse_button(&self, button: Button, direction: Direction) -> bool {
+        match Enigo::new(&Settings::default()) {
+            Ok(mut enigo) => enigo.button(button, direction).is_ok(),
+            Err(e) => {
+                error!("初始化输入设备失败: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// 滚动鼠标滚轮，length 为正时向下（向右）滚动
+    fn scroll(&self, length: i32, axis: Axis) -> bool {
+        match Enigo::new(&Settings::default()) {
+            Ok(mut enigo) => enigo.scroll(length, axis).is_ok(),
+            Err(e) => {
+                error!("初始化输入设备失败: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// 发送键盘按键事件
+    fn key(&self, key: Key, direction: Direction) -> bool {
+        match Enigo::new(&Settings::default()) {
+            Ok(mut enigo) => enigo.key(key, direction).is_ok(),
+            Err(e) => {
+                error!("初始化输入设备失败: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// 输入一段文本，任意 Unicode 字符都直接输入，不依赖键盘布局
+    fn text(&self, text: &str) -> bool {
+        match Enigo::new(&Settings::default()) {
+            Ok(mut enigo) => enigo.text(text).is_ok(),
+            Err(e) => {
+                error!("初始化输入设备失败: {:?}", e);
+                false
+            }
+        }
+    }
+
+    /// 当前按下的按键
+    fn pressed_keys(&self) -> Vec<Keycode> {
+        DeviceState::new().get_keys()
+    }
+
+    /// 已记录但未真正发送的输入事件，只有回放后端会记录
+    fn recorded_events(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// xcap 提供的窗口枚举与截图，macOS 与 X11 后端共用
+pub(crate) fn xcap_windows() -> Vec<WindowInfo> {
+    match xcap::Window::all() {
+        Ok(windows) => windows.iter().map(WindowInfo::from_xcap).collect(),
+        Err(e) => {
+            error!("枚举窗口失败: {:?}", e);
+            Vec::new()
+        }
+    }
+}
+
+pub(crate) fn xcap_capture(window: &WindowInfo) -> Option<RgbaImage> {
+    let windows = xcap::Window::all().ok()?;
+    let target = windows.into_iter().find(|w| w.id() == window.id)?;
+    match target.capture_image() {
+        Ok(image) => Some(image),
+        Err(e) => {
+            error!("截取窗口失败: {:?}", e);
+            None
+        }
+    }
+}
+
+static PLATFORM: OnceCell<Box<dyn Platform>> = OnceCell::new();
+
+/// 设置当前使用的平台后端，只能在首次调用 [`current`] 之前设置
+pub fn set_platform(platform: Box<dyn Platform>) -> bool {
+    PLATFORM.set(platform).is_ok()
+}
+
+/// 当前平台后端，未设置时使用本机默认后端
+pub fn current() -> &'static dyn Platform {
+    PLATFORM.get_or_init(native).as_ref()
+}
+
+#[cfg(target_os = "macos")]
+fn native() -> Box<dyn Platform> {
+    Box::new(macos::MacOsPlatform)
+}
+
+#[cfg(target_os = "linux")]
+fn native() -> Box<dyn Platform> {
+    Box::new(x11::X11Platform)
+}
@@ -0,0 +1,192 @@
+//! 离线回放后端：用保存好的截图代替真实窗口
+//!
+//! 每次截图按顺序返回下一帧，播放到最后一帧后停在最后一帧；
+//! 鼠标、键盘输入不会真正发送，只记录下来并写入日志（以及可选的记录文件）。
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use device_query::Keycode;
+use enigo::{Axis, Button, Direction, Key};
+use image::RgbaImage;
+use log::{error, info};
+
+use super::{Platform, WindowInfo};
+
+/// 回放窗口固定使用的窗口 id
+const REPLAY_WINDOW_ID: u32 = 1;
+
+pub struct ReplayPlatform {
+    title: String,
+    scale_factor: f32,
+    frames: Vec<PathBuf>,
+    width: u32,
+    height: u32,
+    next_frame: Mutex<usize>,
+    events: Mutex<Vec<String>>,
+    /// 最后一次移动到的位置，从 (0, 0) 开始
+    mouse: Mutex<(i32, i32)>,
+    record_file: Option<PathBuf>,
+    pressed_keys: Vec<Keycode>,
+}
+
+/// 展开帧来源：目录按文件名排序取其中的 png/webp，文件直接使用
+fn collect_frames(sources: &[String]) -> Vec<PathBuf> {
+    let mut frames = Vec::new();
+    for source in sources {
+        let path = Path::new(source);
+        if path.is_dir() {
+            let mut files: Vec<PathBuf> = match fs::read_dir(path) {
+                Ok(entries) => entries
+                    .filter_map(|entry| entry.ok().map(|e| e.path()))
+                    .filter(|p| {
+                        p.extension()
+                            .and_then(|e| e.to_str())
+                            .map(|e| matches!(e.to_ascii_lowercase().as_str(), "png" | "webp"))
+                            .unwrap_or(false)
+                    })
+                    .collect(),
+                Err(e) => {
+                    error!("读取回放目录失败，{:?}: {:?}", source, e);
+                    Vec::new()
+                }
+            };
+            files.sort();
+            frames.extend(files);
+        } else {
+            frames.push(path.to_path_buf());
+        }
+    }
+    frames
+}
+
+impl ReplayPlatform {
+    /// 从目录或文件列表创建回放后端，窗口尺寸取第一帧的尺寸。
+    /// `pressed_keys` 为整个回放期间视为一直按下的按键，供 isKeyDown 使用
+    pub fn new(
+        sources: &[String],
+        title: String,
+        scale_factor: f32,
+        record_file: Option<PathBuf>,
+        pressed_keys: Vec<Keycode>,
+    ) -> Result<Self, String> {
+        let frames = collect_frames(sources);
+        let first = frames.first().ok_or_else(|| format!("没有可回放的截图: {:?}", sources))?;
+        let (width, height) = image::image_dimensions(first)
+            .map_err(|e| format!("读取截图失败，{:?}: {:?}", first, e))?;
+        info!("回放模式: {} 帧, 窗口标题: {}, 尺寸: ({}, {})", frames.len(), title, width, height);
+        Ok(ReplayPlatform {
+            title,
+            scale_factor,
+            frames,
+            width,
+            height,
+            next_frame: Mutex::new(0),
+            events: Mutex::new(Vec::new()),
+            mouse: Mutex::new((0, 0)),
+            record_file,
+            pressed_keys,
+        })
+    }
+
+    /// 记录一次输入事件
+    fn record(&self, event: String) {
+        info!("回放模式记录输入: {}", event);
+        if let Some(path) = &self.record_file {
+            let written = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{}", event));
+            if let Err(e) = written {
+                error!("写入回放记录失败，{:?}: {:?}", path, e);
+            }
+        }
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+impl Platform for ReplayPlatform {
+    fn windows(&self) -> Vec<WindowInfo> {
+        vec![WindowInfo {
+            id: REPLAY_WINDOW_ID,
+            pid: std::process::id(),
+            app_name: "replay".to_string(),
+            title: self.title.clone(),
+            x: 0,
+            y: 0,
+            width: (self.width as f32 / self.scale_factor) as u32,
+            height: (self.height as f32 / self.scale_factor) as u32,
+            z: 0,
+            is_minimized: false,
+        }]
+    }
+
+    fn capture(&self, _window: &WindowInfo) -> Option<RgbaImage> {
+        let index = {
+            let mut next_frame = self.next_frame.lock().unwrap();
+            let index = (*next_frame).min(self.frames.len() - 1);
+            *next_frame += 1;
+            index
+        };
+        let path = &self.frames[index];
+        match image::open(path) {
+            Ok(image) => {
+                info!("回放第 {} 帧: {:?}", index, path);
+                Some(image.to_rgba8())
+            }
+            Err(e) => {
+                error!("读取回放帧失败，{:?}: {:?}", path, e);
+                None
+            }
+        }
+    }
+
+    fn activate(&self, _window: &WindowInfo) -> bool {
+        true
+    }
+
+    fn scale_factor(&self) -> f32 {
+        self.scale_factor
+    }
+
+    fn move_mouse(&self, x: i32, y: i32) -> bool {
+        self.record(format!("move {} {}", x, y));
+        *self.mouse.lock().unwrap() = (x, y);
+        true
+    }
+
+    fn mouse_location(&self) -> Option<(i32, i32)> {
+        Some(*self.mouse.lock().unwrap())
+    }
+
+    fn mouse_button(&self, button: Button, direction: Direction) -> bool {
+        self.record(format!("button {:?} {:?}", button, direction));
+        true
+    }
+
+    fn scroll(&self, length: i32, axis: Axis) -> bool {
+        self.record(format!("scroll {} {:?}", length, axis));
+        true
+    }
+
+    fn key(&self, key: Key, direction: Direction) -> bool {
+        self.record(format!("key {:?} {:?}", key, direction));
+        true
+    }
+
+    fn text(&self, text: &str) -> bool {
+        self.record(format!("text {:?}", text));
+        true
+    }
+
+    fn pressed_keys(&self) -> Vec<Keycode> {
+        self.pressed_keys.clone()
+    }
+
+    fn recorded_events(&self) -> Vec<String> {
+        self.events.lock().unwrap().clone()
+    }
+}
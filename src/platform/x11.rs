@@ -0,0 +1,61 @@
+//! Linux(X11) 后端：xcap 负责窗口枚举与截图，x11rb 负责激活窗口
+//!
+//! X11 下窗口坐标、截图与鼠标坐标都是物理像素，缩放系数固定为 1，
+//! 因此可以直接在 Xvfb 中运行脚本。
+
+use image::RgbaImage;
+use log::error;
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    ClientMessageEvent, ConfigureWindowAux, ConnectionExt, EventMask, StackMode,
+};
+use x11rb::CURRENT_TIME;
+
+use super::{xcap_capture, xcap_windows, Platform, WindowInfo};
+
+pub struct X11Platform;
+
+/// 通过 EWMH 的 `_NET_ACTIVE_WINDOW` 请求窗口管理器激活窗口，
+/// 没有窗口管理器时（例如裸 Xvfb）退化为 map + raise
+fn active_window(window: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let (conn, screen_num) = x11rb::connect(None)?;
+    let root = conn.setup().roots[screen_num].root;
+    let net_active_window = conn.intern_atom(false, b"_NET_ACTIVE_WINDOW")?.reply()?.atom;
+
+    // source indication = 1 表示请求来自普通应用
+    let event = ClientMessageEvent::new(32, window, net_active_window, [1, CURRENT_TIME, 0, 0, 0]);
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+        event,
+    )?;
+    conn.map_window(window)?;
+    conn.configure_window(window, &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE))?;
+    conn.flush()?;
+    Ok(())
+}
+
+impl Platform for X11Platform {
+    fn windows(&self) -> Vec<WindowInfo> {
+        xcap_windows()
+    }
+
+    fn capture(&self, window: &WindowInfo) -> Option<RgbaImage> {
+        xcap_capture(window)
+    }
+
+    fn activate(&self, window: &WindowInfo) -> bool {
+        match active_window(window.id) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("激活窗口失败: {:?}", e);
+                false
+            }
+        }
+    }
+
+    fn scale_factor(&self) -> f32 {
+        1.0
+    }
+}
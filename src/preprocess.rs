@@ -0,0 +1,89 @@
+//! 匹配前的图像预处理
+//!
+//! 预处理步骤按顺序同时作用于截图和模板，保证两者经过完全相同的处理。
+
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+use imageproc::contrast::{equalize_histogram, otsu_level, threshold, ThresholdType};
+use imageproc::edges::canny;
+use imageproc::filter::gaussian_blur_f32;
+
+/// 单个预处理步骤
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// 调整对比度，1.0 为不变
+    Contrast(f32),
+    /// 转换为灰度图
+    Grayscale,
+    /// 直方图均衡化
+    Equalize,
+    /// 二值化，None 表示用 Otsu 方法自动选择阈值
+    Threshold(Option<u8>),
+    /// 高斯模糊，参数为 sigma
+    Blur(f32),
+    /// Canny 边缘检测，参数为低、高阈值
+    Edges(f32, f32),
+}
+
+impl Step {
+    /// 按名称创建使用默认参数的步骤
+    pub fn parse(name: &str) -> Option<Step> {
+        match name.to_ascii_lowercase().as_str() {
+            "contrast" => Some(Step::Contrast(1.5)),
+            "grayscale" | "gray" => Some(Step::Grayscale),
+            "equalize" => Some(Step::Equalize),
+            "threshold" | "binarize" => Some(Step::Threshold(None)),
+            "blur" => Some(Step::Blur(1.0)),
+            "edges" | "canny" => Some(Step::Edges(50.0, 100.0)),
+            _ => None,
+        }
+    }
+}
+
+/// 依次应用预处理步骤，没有步骤时直接返回原图的副本
+pub fn apply(image: &DynamicImage, steps: &[Step]) -> DynamicImage {
+    let mut image = image.clone();
+    for step in steps {
+        image = match *step {
+            Step::Contrast(factor) => adjust_contrast(&image, factor),
+            Step::Grayscale => DynamicImage::ImageLuma8(image.to_luma8()),
+            Step::Equalize => DynamicImage::ImageLuma8(equalize_histogram(&image.to_luma8())),
+            Step::Threshold(level) => {
+                let gray = image.to_luma8();
+                let level = level.unwrap_or_else(|| otsu_level(&gray));
+                DynamicImage::ImageLuma8(threshold(&gray, level, ThresholdType::Binary))
+            }
+            Step::Blur(sigma) if sigma > 0.0 => DynamicImage::ImageRgba8(gaussian_blur_f32(&image.to_rgba8(), sigma)),
+            Step::Blur(_) => image,
+            Step::Edges(low, high) => DynamicImage::ImageLuma8(canny(&image.to_luma8(), low, high)),
+        };
+    }
+    image
+}
+
+/// 调整图像对比度
+fn adjust_contrast(image: &DynamicImage, factor: f32) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let mut out_buffer = ImageBuffer::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = image.get_pixel(x, y);
+            let new_pixel = apply_contrast_to_pixel(pixel, factor);
+            out_buffer.put_pixel(x, y, new_pixel);
+        }
+    }
+
+    DynamicImage::ImageRgba8(out_buffer)
+}
+
+/// 对单个像素应用对比度调整
+fn apply_contrast_to_pixel(pixel: Rgba<u8>, factor: f32) -> Rgba<u8> {
+    let f = |c: u8| -> u8 {
+        let c_f32 = c as f32 / 255.0;
+        let adjusted = (128.0 - (128.0 * factor) + (c_f32 * factor * 255.0)) as i32;
+        let clamped = adjusted.clamp(0, 255);
+        clamped as u8
+    };
+
+    Rgba([f(pixel[0]), f(pixel[1]), f(pixel[2]), pixel[3]])
+}
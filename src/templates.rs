@@ -0,0 +1,119 @@
+//! 模板缓存
+//!
+//! 模板图片解码一次后保存在内存中，每次取用时比较文件的修改时间，文件被替换后自动重新读取。
+//! 每个模板还按匹配选项和变换缓存准备好的 `Template`（已预处理、拆分通道并算好统计量），
+//! 循环中反复查找同一个模板时不再读文件、预处理和计算统计量。
+
+use std::collections::HashMap;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use image::DynamicImage;
+use log::{debug, error};
+use once_cell::sync::Lazy;
+
+use crate::matcher::Template;
+
+/// 每个模板最多缓存的变体数（不同选项、缩放比例、角度），超出时清空该模板的变体
+const MAX_VARIANTS: usize = 64;
+
+struct Entry {
+    modified: Option<SystemTime>,
+    image: Arc<DynamicImage>,
+    variants: HashMap<String, Arc<Template>>,
+}
+
+static CACHE: Lazy<Mutex<HashMap<String, Entry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// 文件的修改时间，文件不存在或系统不支持时返回 None
+pub fn modified(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// 读取图片，文件修改时间未变时直接返回缓存
+pub fn load(path: &str) -> Option<Arc<DynamicImage>> {
+    let modified = modified(path);
+    if let Some(entry) = CACHE.lock().unwrap().get(path) {
+        if modified.is_some() && entry.modified == modified {
+            return Some(entry.image.clone());
+        }
+    }
+    let image = match image::open(path) {
+        Ok(image) => Arc::new(image),
+        Err(e) => {
+            error!("打开模版文件失败，{:?}: {:?}", path, e);
+            CACHE.lock().unwrap().remove(path);
+            return None;
+        }
+    };
+    debug!("读取模版文件: {}", path);
+    CACHE.lock().unwrap().insert(path.to_string(), Entry { modified, image: image.clone(), variants: HashMap::new() });
+    Some(image)
+}
+
+/// 取得模板按 key 准备好的变体，没有缓存时调用 build 生成。
+/// image 为 load 返回的图片，期间文件被重新读取时结果不写入缓存
+pub fn prepared(path: &str, image: &Arc<DynamicImage>, key: &str, build: impl FnOnce() -> Option<Template>) -> Option<Arc<Template>> {
+    if let Some(template) = CACHE.lock().unwrap().get(path).filter(|entry| Arc::ptr_eq(&entry.image, image)).and_then(|entry| entry.variants.get(key)) {
+        return Some(template.clone());
+    }
+    let template = Arc::new(build()?);
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(entry) = cache.get_mut(path).filter(|entry| Arc::ptr_eq(&entry.image, image)) {
+        if entry.variants.len() >= MAX_VARIANTS {
+            entry.variants.clear();
+        }
+        entry.variants.insert(key.to_string(), template.clone());
+    }
+    Some(template)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::fs::File;
+    use std::time::Duration;
+
+    use image::{GrayImage, Luma};
+
+    use crate::matcher::ColorMode;
+
+    fn gradient(offset: u8) -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(8, 8, |x, y| Luma([offset.wrapping_add((x * 8 + y) as u8)])))
+    }
+
+    #[test]
+    fn replaced_file_is_reloaded_and_variants_dropped() {
+        let path = std::env::temp_dir().join(format!("action-test-{}-templates.png", std::process::id()));
+        let file = path.to_str().unwrap();
+        let (old, new) = (gradient(0), gradient(100));
+        old.save(&path).unwrap();
+
+        let builds = Cell::new(0);
+        let build = |image: &DynamicImage| {
+            builds.set(builds.get() + 1);
+            Template::new(image, None, ColorMode::Gray)
+        };
+        let first = load(file).unwrap();
+        prepared(file, &first, "gray", || build(&first)).unwrap();
+        assert!(Arc::ptr_eq(&load(file).unwrap(), &first));
+        prepared(file, &first, "gray", || build(&first)).unwrap();
+        assert_eq!(builds.get(), 1);
+
+        // 替换文件并把修改时间推后，保证与原来的不同
+        new.save(&path).unwrap();
+        let later = modified(file).unwrap() + Duration::from_secs(10);
+        File::options().write(true).open(&path).unwrap().set_modified(later).unwrap();
+        let second = load(file).unwrap();
+        assert_eq!(second.to_luma8(), new.to_luma8());
+        prepared(file, &second, "gray", || build(&second)).unwrap();
+        assert_eq!(builds.get(), 2);
+        // 用旧图片准备的结果不会写回新条目
+        prepared(file, &first, "gray", || build(&first)).unwrap();
+        prepared(file, &second, "gray", || build(&second)).unwrap();
+        assert_eq!(builds.get(), 3);
+        let _ = fs::remove_file(&path);
+    }
+}
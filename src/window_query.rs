@@ -0,0 +1,226 @@
+//! 窗口查找条件
+//!
+//! findWindow 除了精确标题外，还可以按标题正则、标题子串、应用（进程）名、PID 或窗口 id 查找，
+//! 多个窗口同时满足条件时按 [`Pick`] 规则选出一个。
+
+use log::debug;
+use regex::{Regex, RegexBuilder};
+
+use crate::platform::WindowInfo;
+
+/// 多个窗口满足条件时的选择规则
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pick {
+    /// 枚举顺序中的第一个
+    First,
+    /// 面积最大的
+    Largest,
+    /// 最靠前的
+    Frontmost,
+}
+
+impl Pick {
+    pub fn parse(name: &str) -> Option<Pick> {
+        match name {
+            "first" => Some(Pick::First),
+            "largest" => Some(Pick::Largest),
+            "frontmost" => Some(Pick::Frontmost),
+            _ => None,
+        }
+    }
+}
+
+/// 由 JavaScript 正则的 source 与 flags 生成标题正则：i、m、s 对应同名的内联标志，
+/// g、u 对单次匹配没有影响，其它标志无法支持，返回错误
+pub fn title_regex(source: &str, flags: &str) -> Result<Regex, String> {
+    let mut builder = RegexBuilder::new(source);
+    for flag in flags.chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            'g' | 'u' => &mut builder,
+            _ => return Err(format!("不支持的正则标志: {}", flag)),
+        };
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+/// 窗口查找条件，所有设置了的条件都需要满足
+#[derive(Debug, Clone)]
+pub struct WindowQuery {
+    pub title: Option<String>,
+    pub title_contains: Option<String>,
+    pub title_regex: Option<Regex>,
+    pub app_name: Option<String>,
+    pub pid: Option<u32>,
+    pub id: Option<u32>,
+    pub pick: Pick,
+}
+
+impl Default for WindowQuery {
+    fn default() -> Self {
+        WindowQuery {
+            title: None,
+            title_contains: None,
+            title_regex: None,
+            app_name: None,
+            pid: None,
+            id: None,
+            pick: Pick::First,
+        }
+    }
+}
+
+impl WindowQuery {
+    /// 按精确标题查找，与原来的 findWindow(title) 一致
+    pub fn by_title(title: String) -> Self {
+        WindowQuery {
+            title: Some(title),
+            ..WindowQuery::default()
+        }
+    }
+
+    /// 窗口不满足的第一个条件，全部满足时返回 None
+    fn rejection(&self, window: &WindowInfo) -> Option<String> {
+        if let Some(id) = self.id {
+            if window.id != id {
+                return Some(format!("id 不是 {}", id));
+            }
+        }
+        if let Some(pid) = self.pid {
+            if window.pid != pid {
+                return Some(format!("pid 不是 {}", pid));
+            }
+        }
+        if let Some(title) = &self.title {
+            if &window.title != title {
+                return Some(format!("标题不等于 {:?}", title));
+            }
+        }
+        if let Some(part) = &self.title_contains {
+            if !window.title.contains(part.as_str()) {
+                return Some(format!("标题不包含 {:?}", part));
+            }
+        }
+        if let Some(regex) = &self.title_regex {
+            if !regex.is_match(&window.title) {
+                return Some(format!("标题不匹配 /{}/", regex));
+            }
+        }
+        if let Some(app_name) = &self.app_name {
+            if !window.app_name.eq_ignore_ascii_case(app_name) {
+                return Some(format!("应用名不是 {:?}", app_name));
+            }
+        }
+        None
+    }
+
+    /// 返回所有满足条件的窗口，保持枚举顺序，被排除的窗口及原因写入 debug 日志
+    pub fn filter(&self, windows: Vec<WindowInfo>) -> Vec<WindowInfo> {
+        windows
+            .into_iter()
+            .filter(|window| match self.rejection(window) {
+                Some(reason) => {
+                    debug!("排除窗口 [id={} pid={} app={:?} title={:?}]: {}", window.id, window.pid, window.app_name, window.title, reason);
+                    false
+                }
+                None => true,
+            })
+            .collect()
+    }
+
+    /// 按选择规则从满足条件的窗口中选出一个
+    pub fn select(&self, windows: Vec<WindowInfo>) -> Option<WindowInfo> {
+        let candidates = self.filter(windows);
+        if candidates.len() > 1 {
+            debug!("{} 个窗口满足条件，按 {:?} 选择", candidates.len(), self.pick);
+        }
+        let mut candidates = candidates.into_iter();
+        match self.pick {
+            Pick::First => candidates.next(),
+            // max_by_key 在相等时取最后一个，这里需要的是枚举顺序中的第一个
+            Pick::Largest => candidates.rev().max_by_key(|w| w.width as u64 * w.height as u64),
+            Pick::Frontmost => candidates.rev().max_by_key(|w| w.z),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: u32, pid: u32, app_name: &str, title: &str, size: (u32, u32), z: i32) -> WindowInfo {
+        WindowInfo {
+            id,
+            pid,
+            app_name: app_name.to_string(),
+            title: title.to_string(),
+            x: 0,
+            y: 0,
+            width: size.0,
+            height: size.1,
+            z,
+            is_minimized: false,
+        }
+    }
+
+    fn windows() -> Vec<WindowInfo> {
+        vec![
+            window(1, 100, "Finder", "下载", (800, 600), 3),
+            window(2, 200, "GameClient", "神器传说 - 1区", (1024, 768), 1),
+            window(3, 200, "GameClient", "神器传说 - 2区", (1024, 768), 5),
+            window(4, 300, "Browser", "神器传说 攻略", (1280, 800), 5),
+        ]
+    }
+
+    fn ids(query: &WindowQuery) -> Vec<u32> {
+        query.filter(windows()).iter().map(|w| w.id).collect()
+    }
+
+    #[test]
+    fn conditions_are_combined() {
+        assert_eq!(ids(&WindowQuery::by_title("神器传说 - 2区".to_string())), vec![3]);
+        assert_eq!(ids(&WindowQuery::by_title("神器传说".to_string())), Vec::<u32>::new());
+        let contains = WindowQuery { title_contains: Some("神器传说".to_string()), ..WindowQuery::default() };
+        assert_eq!(ids(&contains), vec![2, 3, 4]);
+        let app = WindowQuery { app_name: Some("gameclient".to_string()), ..contains.clone() };
+        assert_eq!(ids(&app), vec![2, 3]);
+        let pid = WindowQuery { pid: Some(200), title_regex: Some(Regex::new("1区$").unwrap()), ..WindowQuery::default() };
+        assert_eq!(ids(&pid), vec![2]);
+        let id = WindowQuery { id: Some(4), pid: Some(200), ..WindowQuery::default() };
+        assert_eq!(ids(&id), Vec::<u32>::new());
+        assert_eq!(ids(&WindowQuery::default()), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn regex_flags() {
+        let query = |regex: Regex| WindowQuery { title_regex: Some(regex), ..WindowQuery::default() };
+        assert_eq!(ids(&query(title_regex("^finder|下载$", "").unwrap())), vec![1]);
+        assert!(title_regex("^DOWNLOAD$", "i").unwrap().is_match("download"));
+        assert!(!title_regex("^DOWNLOAD$", "").unwrap().is_match("download"));
+        assert!(title_regex("^b$", "m").unwrap().is_match("a\nb"));
+        assert!(!title_regex("^b$", "").unwrap().is_match("a\nb"));
+        assert!(title_regex("a.b", "s").unwrap().is_match("a\nb"));
+        assert!(!title_regex("a.b", "").unwrap().is_match("a\nb"));
+        assert!(title_regex("传说", "gu").is_ok());
+        assert!(title_regex("传说", "y").is_err());
+        assert!(title_regex("(", "").is_err());
+    }
+
+    #[test]
+    fn pick_rules_and_ties() {
+        let query = |pick| WindowQuery { title_contains: Some("神器传说".to_string()), pick, ..WindowQuery::default() };
+        assert_eq!(query(Pick::First).select(windows()).map(|w| w.id), Some(2));
+        assert_eq!(query(Pick::Largest).select(windows()).map(|w| w.id), Some(4));
+        // 3 与 4 同样靠前，取枚举顺序中的第一个
+        assert_eq!(query(Pick::Frontmost).select(windows()).map(|w| w.id), Some(3));
+        // 2 与 3 面积相同
+        let game = WindowQuery { app_name: Some("GameClient".to_string()), pick: Pick::Largest, ..WindowQuery::default() };
+        assert_eq!(game.select(windows()).map(|w| w.id), Some(2));
+        let none = WindowQuery { pid: Some(999), pick: Pick::Largest, ..WindowQuery::default() };
+        assert!(none.select(windows()).is_none());
+        assert_eq!(Pick::parse("frontmost"), Some(Pick::Frontmost));
+        assert_eq!(Pick::parse("last"), None);
+    }
+}
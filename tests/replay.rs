@@ -0,0 +1,283 @@
+//! 回放模式的集成测试：用 tests/fixtures/replay 中保存的截图运行脚本，检查查找结果与记录的输入
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/replay").join(name)
+}
+
+/// 把脚本写入临时文件，带上命令行选项运行
+fn run_script(name: &str, script: &str, options: &[&str]) -> Output {
+    let script_file = std::env::temp_dir().join(format!("action-replay-{}-{}.js", std::process::id(), name));
+    fs::write(&script_file, script).unwrap();
+    let output = Command::new(env!("CARGO_BIN_EXE_action")).arg(&script_file).args(options).output().unwrap();
+    let _ = fs::remove_file(&script_file);
+    output
+}
+
+/// 带 --replay-record 运行脚本，限定时间内必须退出，返回退出状态与记录的输入
+fn run_recorded(name: &str, script: &str, options: &[&str]) -> (bool, Vec<String>) {
+    let dir = std::env::temp_dir();
+    let script_file = dir.join(format!("action-replay-{}-{}.js", std::process::id(), name));
+    let record_file = dir.join(format!("action-replay-{}-{}.log", std::process::id(), name));
+    fs::write(&script_file, script).unwrap();
+    let _ = fs::remove_file(&record_file);
+    let mut child = Command::new(env!("CARGO_BIN_EXE_action"))
+        .arg(&script_file)
+        .args(options)
+        .args(["--replay-record", record_file.to_str().unwrap()])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            let _ = child.kill();
+            panic!("脚本中止后没有退出");
+        }
+        thread::sleep(Duration::from_millis(50));
+    };
+    let events = fs::read_to_string(&record_file).unwrap_or_default().lines().map(String::from).collect();
+    let _ = fs::remove_file(&script_file);
+    let _ = fs::remove_file(&record_file);
+    (status.success(), events)
+}
+
+#[test]
+fn find_template_and_click_are_recorded() {
+    // 第一帧在 (60, 40) 有按钮，第二帧没有
+    let script = format!(
+        r#"
+        if (!findWindow("Replay")) throw new Error("没有找到回放窗口");
+        var ncc = findTemplate({button:?});
+        if (ncc < 0.99) throw new Error("匹配率 " + ncc);
+        if (findX() != 60 || findY() != 40) throw new Error("位置 " + findX() + ", " + findY());
+        click();
+        var events = JSON.stringify(replayEvents());
+        if (events != JSON.stringify(["move 72 48", "button Left Click"])) throw new Error("记录的输入 " + events);
+        ncc = findTemplate({button:?});
+        if (ncc >= 0.9) throw new Error("第二帧不应找到按钮: " + ncc);
+        "#,
+        button = fixture("button.png").to_str().unwrap(),
+    );
+    let frames = fixture("frames");
+    let output = run_script("click", &script, &["--replay", frames.to_str().unwrap(), "--replay-title", "Replay"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn invalid_replay_scale_is_rejected() {
+    let frames = fixture("frames");
+    for scale in ["0", "-1", "abc", "inf"] {
+        let output = run_script("scale", "", &["--replay", frames.to_str().unwrap(), "--replay-scale", scale]);
+        assert_eq!(output.status.code(), Some(2), "--replay-scale {}", scale);
+    }
+}
+
+#[test]
+fn get_pixel_reuses_last_capture() {
+    // snapshot 取得第一帧；getPixel 读的仍是第一帧，不会消耗第二帧
+    let pixel = image::open(fixture("button.png")).unwrap().to_rgb8().get_pixel(0, 0).0;
+    let hex = format!("#{:02X}{:02X}{:02X}", pixel[0], pixel[1], pixel[2]);
+    let script = format!(
+        r#"
+        findWindow("Replay");
+        snapshot();
+        for (var i = 0; i < 3; i++) {{
+            var hex = getPixel(60, 40).hex;
+            if (hex != {hex:?}) throw new Error("像素 " + hex);
+        }}
+        // 下一次截图才是第二帧
+        if (findTemplate({button:?}) >= 0.9) throw new Error("第二帧不应找到按钮");
+        try {{ findColor(-1, 0); throw new Error("负数颜色没有报错"); }} catch (e) {{ if (!(e instanceof RangeError)) throw e; }}
+        try {{ findColor(NaN, 0); throw new Error("NaN 颜色没有报错"); }} catch (e) {{ if (!(e instanceof RangeError)) throw e; }}
+        "#,
+        button = fixture("button.png").to_str().unwrap(),
+    );
+    let frames = fixture("frames");
+    let output = run_script("pixel", &script, &["--replay", frames.to_str().unwrap(), "--replay-title", "Replay"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn negative_coordinates_stay_signed() {
+    // 回放窗口位于 (0, 0)，窗口外的负坐标原样传给鼠标
+    let script = r#"
+        findWindow("Replay");
+        click(-5, 12);
+        drag(-10, -20, 30, 40);
+        // 拖动过程中的中间点不检查，只看起点和终点
+        var events = replayEvents();
+        var recorded = JSON.stringify(events.slice(0, 4).concat(events.slice(-2)));
+        var expected = ["move -5 12", "button Left Click", "move -10 -20", "button Left Press", "move 30 40", "button Left Release"];
+        if (recorded != JSON.stringify(expected)) throw new Error("记录的输入 " + recorded);
+    "#;
+    let frames = fixture("frames");
+    let output = run_script("signed", script, &["--replay", frames.to_str().unwrap(), "--replay-title", "Replay"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn non_finite_mouse_options_are_rejected() {
+    let script = r#"
+        var invalid = [{jitter: NaN}, {jitter: Infinity}, {overshoot: NaN}, {overshoot: -Infinity}, {seed: NaN},
+            {clickDelay: Infinity}, {clickDelay: [10, Infinity]}, {pressDuration: NaN}];
+        invalid.forEach(function (options) {
+            try { setMouseOptions(options); } catch (e) { if (e instanceof RangeError) return; throw e; }
+            throw new Error("没有报错: " + JSON.stringify(options));
+        });
+        // 出错的选项不会生效，之后的点击仍然正常
+        findWindow("Replay");
+        setMouseOptions({jitter: 1, seed: 3, clickDelay: 0});
+        if (!click(20, 20)) throw new Error("点击失败");
+    "#;
+    let frames = fixture("frames");
+    let output = run_script("mouse-options", script, &["--replay", frames.to_str().unwrap(), "--replay-title", "Replay"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn hotkeys_after_a_throwing_callback_still_run() {
+    // 两个热键在同一次轮询中触发，第一个回调抛出异常，第二个留在队列中下次执行
+    let script = r#"
+        var ran = [];
+        onHotkey("F1", function () { ran.push("first"); throw new Error("first"); });
+        onHotkey("F1", function () { ran.push("second"); });
+        try { sleep(200); throw new Error("异常没有传出"); } catch (e) { if (e.message != "first") throw e; }
+        sleep(50);
+        if (JSON.stringify(ran) != JSON.stringify(["first", "second"])) throw new Error("执行的回调 " + JSON.stringify(ran));
+    "#;
+    let frames = fixture("frames");
+    let output = run_script("requeue", script, &["--replay", frames.to_str().unwrap(), "--replay-keys", "F1"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+/// 启动时不启用中止热键，脚本按下按键之后再把 F1 设为中止热键，回放按键 F1 一直按着，下一次检测时中止
+const ABORT_AFTER_PRESS: &str = r#"
+    keyDown("LShift");
+    mouseDown();
+    setControlKeys({abort: ["F1"]});
+"#;
+
+#[test]
+fn abort_runs_finally_and_releases_input() {
+    let script = format!(
+        r#"{ABORT_AFTER_PRESS}
+        try {{
+            while (true) sleep(20);
+        }} finally {{
+            keyUp("LShift");
+            mouseUp();
+            console.log("finally 执行完毕");
+        }}
+        "#
+    );
+    let frames = fixture("frames");
+    let (success, events) = run_recorded("abort-finally", &script, &["--replay", frames.to_str().unwrap(), "--replay-keys", "F1", "--abort-keys", ""]);
+    assert!(success);
+    assert_eq!(events, ["key LShift Press", "button Left Press", "key LShift Release", "button Left Release"]);
+}
+
+#[test]
+fn abort_ends_a_loop_that_catches_the_error() {
+    // catch 吞掉中止错误后的下一次调用直接退出，并松开还按着的键
+    let script = format!(
+        r#"{ABORT_AFTER_PRESS}
+        findWindow("Replay");
+        while (true) {{
+            try {{ waitForTemplate({button:?}, {{timeout: 50}}); }} catch (e) {{}}
+        }}
+        "#,
+        button = fixture("button.png").to_str().unwrap(),
+    );
+    let frames = fixture("frames");
+    let (success, events) = run_recorded("abort-loop", &script, &["--replay", frames.to_str().unwrap(), "--replay-title", "Replay", "--replay-keys", "F1", "--abort-keys", ""]);
+    assert!(success);
+    assert_eq!(events, ["key LShift Press", "button Left Press", "button Left Release", "key LShift Release"]);
+}
+
+#[test]
+fn wait_until_gone_throws_when_the_search_cannot_run() {
+    // 读取不了模板、没有目标窗口时不能当作已经消失
+    let script = format!(
+        r#"
+        function expectError(name, wait) {{
+            try {{ wait(); }} catch (e) {{ return; }}
+            throw new Error(name + " 没有报错");
+        }}
+        expectError("没有目标窗口", function () {{ waitUntilGone({button:?}, {{timeout: 100}}); }});
+        findWindow("Replay");
+        // 第一帧有按钮，之后的帧没有
+        waitForTemplate({button:?}, {{timeout: 100}});
+        expectError("模板不存在", function () {{ waitUntilGone("no-such-template.png", {{timeout: 100}}); }});
+        expectError("模板不存在", function () {{ waitForTemplate("no-such-template.png", {{timeout: 100}}); }});
+        expectError("区域小于模板", function () {{ waitUntilGone({button:?}, 0, 0, 4, 4, {{timeout: 100}}); }});
+        if (waitUntilGone({button:?}, {{timeout: 1000, interval: 10}}) !== true) throw new Error("按钮没有消失");
+        "#,
+        button = fixture("button.png").to_str().unwrap(),
+    );
+    let frames = fixture("frames");
+    let output = run_script("gone", &script, &["--replay", frames.to_str().unwrap(), "--replay-title", "Replay"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn invalid_drag_duration_is_rejected() {
+    // 无效的时长在按下按键之前就报错，不会按着按键卡住
+    let script = r#"
+        findWindow("Replay");
+        [Infinity, NaN, -1, 1e308].forEach(function (duration) {
+            try { drag(0, 0, 10, 10, {duration: duration}); } catch (e) { if (e instanceof RangeError) return; throw e; }
+            throw new Error("没有报错: " + duration);
+        });
+        if (replayEvents().length != 0) throw new Error("记录的输入 " + JSON.stringify(replayEvents()));
+    "#;
+    let frames = fixture("frames");
+    let output = run_script("drag-duration", script, &["--replay", frames.to_str().unwrap(), "--replay-title", "Replay"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn invalid_preprocess_parameters_are_rejected() {
+    // 写错的参数不能悄悄关掉预处理
+    let script = r#"
+        var invalid = [{blur: 0}, {blur: -1}, {blur: NaN}, {blur: Infinity},
+            {edges: [100, 50]}, {edges: [NaN, 100]}, {edges: [50, Infinity]}, {edges: [50]}];
+        invalid.forEach(function (step) {
+            try { setMatchOptions({preprocess: [step]}); } catch (e) { if (e instanceof RangeError) return; throw e; }
+            throw new Error("没有报错: " + JSON.stringify(step));
+        });
+        setMatchOptions({preprocess: [{blur: 0.5}, {edges: [50, 50]}]});
+    "#;
+    let frames = fixture("frames");
+    let output = run_script("preprocess", script, &["--replay", frames.to_str().unwrap()]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn default_control_keys_are_off_under_replay() {
+    // 回放的按键包含默认的暂停与中止组合键，脚本照常运行到结束
+    let script = r#"
+        sleep(200);
+        if (isPaused()) throw new Error("不应暂停");
+        console.log("运行结束");
+    "#;
+    let frames = fixture("frames");
+    let keys = "LControl,LShift,Q,A";
+    let (success, _) = run_recorded("control-defaults", script, &["--replay", frames.to_str().unwrap(), "--replay-keys", keys]);
+    assert!(success);
+    // 命令行明确指定的热键仍然生效
+    let script = r#"
+        try { while (true) sleep(20); } catch (e) { if (e.message != "脚本已中止") throw e; }
+    "#;
+    let (success, _) = run_recorded("control-explicit", script, &["--replay", frames.to_str().unwrap(), "--replay-keys", keys, "--abort-keys", "LControl,Q"]);
+    assert!(success);
+}
@@ -0,0 +1,138 @@
+//! X11 后端的集成测试：在 X 服务器上创建一个已知内容的窗口，
+//! 运行脚本 findWindow / findTemplate / click，检查窗口收到的点击位置。
+//!
+//! 需要 DISPLAY，默认不运行，用 `scripts/xvfb-test.sh` 在 Xvfb 中执行：
+//! `cargo test --test x11 -- --ignored`
+
+#![cfg(target_os = "linux")]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use image::{Rgba, RgbaImage};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{
+    AtomEnum, ConnectionExt, CreateGCAux, CreateWindowAux, EventMask, ImageFormat, PropMode, WindowClass,
+};
+use x11rb::protocol::Event;
+use x11rb::wrapper::ConnectionExt as _;
+
+const TITLE: &str = "auto-action x11 test";
+const WIDTH: u16 = 240;
+const HEIGHT: u16 = 160;
+/// 模板在窗口中的位置与尺寸
+const TEMPLATE: (u32, u32, u32, u32) = (70, 50, 32, 24);
+
+/// 固定种子的伪随机 RGB 图
+fn noise(width: u32, height: u32) -> RgbaImage {
+    let mut state = 0x2545f4914f6cdd1du64;
+    RgbaImage::from_fn(width, height, |_, _| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        let v = (state >> 40) as u32;
+        Rgba([(v >> 16) as u8, (v >> 8) as u8, v as u8, 255])
+    })
+}
+
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("action-x11-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+#[ignore = "需要 X 服务器，用 scripts/xvfb-test.sh 运行"]
+fn find_template_and_click_on_x11() {
+    let (conn, screen_num) = x11rb::connect(None).expect("需要 DISPLAY，例如在 Xvfb 中运行 scripts/xvfb-test.sh");
+    let screen = conn.setup().roots[screen_num].clone();
+    let atom = |name: &str| conn.intern_atom(false, name.as_bytes()).unwrap().reply().unwrap().atom;
+
+    // 窗口内容放在背景 pixmap 中，没有合成器时重绘也不会丢失
+    let content = noise(WIDTH as u32, HEIGHT as u32);
+    let pixmap = conn.generate_id().unwrap();
+    conn.create_pixmap(screen.root_depth, pixmap, screen.root, WIDTH, HEIGHT).unwrap();
+    let gc = conn.generate_id().unwrap();
+    conn.create_gc(gc, pixmap, &CreateGCAux::new()).unwrap();
+    // 24 位深度的 ZPixmap 每像素 4 字节，按 BGRX 存放；分块发送避免超过请求长度
+    for top in (0..HEIGHT).step_by(32) {
+        let rows = (HEIGHT - top).min(32);
+        let mut data = Vec::with_capacity(WIDTH as usize * rows as usize * 4);
+        for y in top..top + rows {
+            for x in 0..WIDTH {
+                let p = content.get_pixel(x as u32, y as u32);
+                data.extend_from_slice(&[p[2], p[1], p[0], 0]);
+            }
+        }
+        conn.put_image(ImageFormat::Z_PIXMAP, pixmap, gc, WIDTH, rows, 0, top as i16, 0, screen.root_depth, &data).unwrap();
+    }
+
+    let window = conn.generate_id().unwrap();
+    conn.create_window(
+        x11rb::COPY_DEPTH_FROM_PARENT,
+        window,
+        screen.root,
+        40,
+        30,
+        WIDTH,
+        HEIGHT,
+        0,
+        WindowClass::INPUT_OUTPUT,
+        x11rb::COPY_FROM_PARENT,
+        &CreateWindowAux::new().background_pixmap(pixmap).event_mask(EventMask::BUTTON_PRESS),
+    )
+    .unwrap();
+    conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_NAME, AtomEnum::STRING, TITLE.as_bytes()).unwrap();
+    conn.change_property8(PropMode::REPLACE, window, AtomEnum::WM_CLASS, AtomEnum::STRING, b"action-test\0action-test\0").unwrap();
+    conn.change_property32(PropMode::REPLACE, window, atom("_NET_WM_PID"), AtomEnum::CARDINAL, &[std::process::id()]).unwrap();
+    // xcap 读取窗口状态时要求这些原子已存在，窗口管理器通常会创建它们
+    for name in ["_NET_WM_STATE", "_NET_WM_STATE_HIDDEN", "_NET_WM_STATE_MAXIMIZED_VERT", "_NET_WM_STATE_MAXIMIZED_HORZ"] {
+        atom(name);
+    }
+    conn.map_window(window).unwrap();
+    // xcap 按 _NET_CLIENT_LIST_STACKING 枚举窗口；没有窗口管理器（裸 Xvfb）时由测试自己发布
+    let client_list = atom("_NET_CLIENT_LIST_STACKING");
+    let published = conn.get_property(false, screen.root, client_list, AtomEnum::ANY, 0, 1).unwrap().reply().unwrap();
+    if published.type_ == x11rb::NONE {
+        conn.change_property32(PropMode::REPLACE, screen.root, client_list, AtomEnum::WINDOW, &[window]).unwrap();
+    }
+    conn.flush().unwrap();
+
+    let dir = temp_dir();
+    let template_file = dir.join("template.png");
+    let (tx, ty, tw, th) = TEMPLATE;
+    image::imageops::crop_imm(&content, tx, ty, tw, th).to_image().save(&template_file).unwrap();
+    let script_file = dir.join("x11.js");
+    let script = format!(
+        r#"
+        if (!findWindow({title:?})) throw new Error("没有找到窗口");
+        var ncc = findTemplate({template:?});
+        if (ncc < 0.99) throw new Error("匹配率 " + ncc);
+        if (findX() != {tx} || findY() != {ty}) throw new Error("位置 " + findX() + ", " + findY());
+        setMouseOptions({{clickDelay: 100}});
+        if (!click()) throw new Error("点击失败");
+        "#,
+        title = TITLE,
+        template = template_file.to_str().unwrap(),
+    );
+    fs::write(&script_file, script).unwrap();
+
+    let status = Command::new(env!("CARGO_BIN_EXE_action")).arg(&script_file).status().unwrap();
+    assert!(status.success(), "脚本运行失败: {:?}", status);
+
+    // 点击落在模板中心，坐标相对窗口
+    let expected = ((tx + tw / 2) as i16, (ty + th / 2) as i16);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        match conn.poll_for_event().unwrap() {
+            Some(Event::ButtonPress(event)) if event.event == window => {
+                assert_eq!((event.event_x, event.event_y), expected);
+                break;
+            }
+            Some(_) => {}
+            None if Instant::now() < deadline => std::thread::sleep(Duration::from_millis(20)),
+            None => panic!("窗口没有收到点击"),
+        }
+    }
+    let _ = fs::remove_dir_all(&dir);
+}